@@ -1,6 +1,5 @@
-use std::fs;
-use std::io;
 use std::process::Command;
+use crate::error::{CompileError, ErrorCode};
 
 pub struct Assembler {
    s_filename: String,
@@ -14,21 +13,22 @@ impl Drop for Assembler {
 }
 
 impl Assembler {
-   pub fn new(s_filename_: &String) -> Self {
+   pub fn new(s_filename_: &str) -> Self {
       Self {
-         s_filename: s_filename_.clone(),
+         s_filename: s_filename_.to_string(),
          output_filename: s_filename_.replace(".s", "")
       }
    }
 
-   pub fn process(&mut self) -> Result<(), io::Error> {
-      let _ = Command::new("gcc")
+   pub fn process(&mut self) -> Result<(), CompileError> {
+      let output = Command::new("gcc")
           .arg(&self.s_filename)
           .arg("-o")
           .arg(&self.output_filename)
-          .output();
-
-      fs::read_to_string(&self.s_filename)?;
+          .output()?;
+      if !output.status.success() {
+         return Err(CompileError::from_gcc(ErrorCode::AssemblerFailed, "Assembling and linking with gcc", &output));
+      }
       Ok(())
    }
 }
\ No newline at end of file
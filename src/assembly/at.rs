@@ -1,53 +1,61 @@
 use std::fs;
 use std::io::Write;
+use crate::error::CompileError;
 use crate::tacky::tacky_ast;
 use super::stack_allocator::StackAllocator;
 
 pub enum Program {
-   Program(FuncDef)
+   Program(Vec<FuncDef>)
 }
 
 impl Program {
    pub fn pretty_print(&self) {
       println!("Program(");
       match self {
-         Program::Program(f) => {
-            f.pretty_print(1);
+         Program::Program(funcs) => {
+            for f in funcs {
+               f.pretty_print(1);
+            }
          }
       }
       println!(")");
    }
 
-   pub fn parse(program: &tacky_ast::Program) -> Result<Self, String> {
-      let function_def: FuncDef;
-      match program {
-         tacky_ast::Program::Program(func_def) => {
-            function_def = FuncDef::parse(func_def)?;
+   pub fn parse(program: &tacky_ast::Program) -> Result<Self, CompileError> {
+      let function_defs = match program {
+         tacky_ast::Program::Program(func_defs) => {
+            func_defs.iter().map(FuncDef::parse).collect::<Result<Vec<_>, _>>()?
          }
-      }
-      Ok(Program::Program(function_def))
+      };
+      Ok(Program::Program(function_defs))
    }
 
    pub fn replace_pseudoregs(&mut self) {
       match self {
-         Program::Program(f) => {
-            f.replace_pseudoregs();
+         Program::Program(funcs) => {
+            for f in funcs {
+               f.replace_pseudoregs();
+            }
          }
       }
    }
 
    pub fn register_fixup(&mut self) {
       match self {
-         Program::Program(f) => {
-            f.register_fixup();
+         Program::Program(funcs) => {
+            for f in funcs {
+               f.register_fixup();
+            }
          }
       }
    }
 
    pub fn write(&self, text: &mut fs::File) -> std::io::Result<()> {
       match self {
-         Program::Program(p) => {
-            p.write(text)?;
+         Program::Program(funcs) => {
+            for f in funcs {
+               f.write(text)?;
+            }
          }
       }
       Ok(())
@@ -56,14 +64,16 @@ impl Program {
 
 pub struct Function {
    name: String,
+   global: bool,
    instrs: Vec<Inst>,
    stack_allocator: StackAllocator
 }
 
 impl Function {
-   pub fn new(name_: &String) -> Self {
+   pub fn new(name_: &String, global_: bool) -> Self {
       Function {
          name: name_.to_string(),
+         global: global_,
          instrs: Vec::new(),
          stack_allocator: StackAllocator::new()
       }
@@ -71,6 +81,7 @@ impl Function {
 
    pub fn pretty_print(&self, indent_level: usize) {
       println!("{:indent$}Name({name})", "", indent=indent_level*3, name=self.name);
+      println!("{:indent$}Global({global})", "", indent=indent_level*3, global=self.global);
       println!("{:indent$}Instrs(", "", indent=indent_level*3);
       for instr in &self.instrs {
          instr.pretty_print(indent_level+1);
@@ -78,7 +89,9 @@ impl Function {
    }
 
    pub fn write(&self, text: &mut fs::File) -> std::io::Result<()> {
-      writeln!(text, "\t.globl _{}", self.name)?;
+      if self.global {
+         writeln!(text, "\t.globl _{}", self.name)?;
+      }
       writeln!(text, "_{}:", self.name)?;
       writeln!(text, "\tpushq\t%rbp")?;
       writeln!(text, "\tmovq\t%rsp, %rbp")?;
@@ -88,8 +101,8 @@ impl Function {
       Ok(())
    }
 
-   pub fn parse(function: &tacky_ast::Function) -> Result<Self, String> {
-      let mut at_func: Function = Function::new(&function.identifier);
+   pub fn parse(function: &tacky_ast::Function) -> Result<Self, CompileError> {
+      let mut at_func: Function = Function::new(&function.identifier, function.global);
 
       for instr in &function.instrs {
          match instr {
@@ -139,25 +152,16 @@ impl Function {
       for instr in &mut self.instrs {
          match instr {
             Inst::Mov(m) => {
-               match &m.src {
-                  Operand::PseudoReg(p) => {
-                     m.src = Operand::Stack(self.stack_allocator.allocate(p.to_string(), 4));
-                  },
-                  _ => ()
+               if let Operand::PseudoReg(p) = &m.src {
+                  m.src = Operand::Stack(self.stack_allocator.allocate(p.to_string(), 4));
                }
-               match &m.dst {
-                  Operand::PseudoReg(p) => {
-                     m.dst = Operand::Stack(self.stack_allocator.allocate(p.to_string(), 4));
-                  },
-                  _ => ()
+               if let Operand::PseudoReg(p) = &m.dst {
+                  m.dst = Operand::Stack(self.stack_allocator.allocate(p.to_string(), 4));
                }
             },
             Inst::Unary(_, operand) => {
-               match &operand {
-                  Operand::PseudoReg(p) => {
-                     *operand = Operand::Stack(self.stack_allocator.allocate(p.to_string(), 4));
-                  },
-                  _ => ()
+               if let Operand::PseudoReg(p) = &operand {
+                  *operand = Operand::Stack(self.stack_allocator.allocate(p.to_string(), 4));
                }
             },
             _ => ()
@@ -203,13 +207,10 @@ impl FuncDef {
       println!("{:indent$})", "", indent=indent_level*3);
    }
 
-   pub fn parse(func_def: &tacky_ast::FuncDef) -> Result<Self, String> {
-      let at_func: Function;
-      match func_def {
-         tacky_ast::FuncDef::Function(function) => {
-            at_func = Function::parse(&function)?;
-         }
-      }
+   pub fn parse(func_def: &tacky_ast::FuncDef) -> Result<Self, CompileError> {
+      let at_func = match func_def {
+         tacky_ast::FuncDef::Function(function) => Function::parse(function)?,
+      };
       Ok(FuncDef::Function(at_func))
    }
 
@@ -305,7 +306,7 @@ impl Inst {
          Inst::Unary(op, operand ) => {
             op.write(text)?;
             operand.write(text)?;
-            writeln!(text, "")?;
+            writeln!(text)?;
          }
       }
       Ok(())
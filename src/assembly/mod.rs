@@ -2,6 +2,7 @@ pub mod at;
 pub mod stack_allocator;
 
 use at::Program;
+use crate::error::CompileError;
 use crate::tacky::tacky_ast;
 
 pub struct AssemblyGen {
@@ -15,7 +16,7 @@ impl AssemblyGen {
       }
    }
 
-   pub fn parse(&mut self) -> Result<Program, String> {
+   pub fn parse(&mut self) -> Result<Program, CompileError> {
       let mut program = Program::parse(&self.program)?;
       program.replace_pseudoregs();
       program.register_fixup();
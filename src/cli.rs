@@ -1,23 +1,77 @@
 use clap::{
-    builder::ArgPredicate, Args, Parser
+    builder::ArgPredicate, Args, Parser, ValueEnum
 };
 
+/// Exit statuses, one per stage that can fail, so that scripts can tell failures apart.
+const EXIT_CODES: &str = "\
+Exit status:
+  0  success
+  2  invalid command line
+  3  lexer error
+  4  parse error
+  5  semantic error
+  6  code generation error
+  7  toolchain error (gcc or file system)
+  101  internal compiler error (a panic)";
+
 #[derive(Parser,Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = EXIT_CODES)]
 pub struct Cli {
     /// Source file to compile
     pub file: String,
 
+    /// Lex the source file directly instead of running it through `gcc -E`
+    #[arg(long)]
+    pub no_preprocess: bool,
+
+    /// Language standard to compile for
+    #[arg(long = "std", value_enum, default_value_t = Standard::C17)]
+    pub std: Standard,
+
     #[command(flatten)]
     pub command: Command,
 }
 
 impl Cli {
    pub fn do_parse() -> Cli {
-      Cli::parse()
+      // Accept gcc's single-dash `-std=c99` spelling as well as `--std=c99`.
+      let args = std::env::args().map(|arg| {
+         if arg.starts_with("-std=") {
+            format!("-{}", arg)
+         } else {
+            arg
+         }
+      });
+      Cli::parse_from(args)
    }
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Standard {
+    #[value(alias = "c90")]
+    C89,
+    C99,
+    C11,
+    #[value(alias = "c18")]
+    C17,
+    #[value(alias = "c2x")]
+    C23,
+}
+
+impl Standard {
+    /// The `-std=` value to preprocess with. These are the GNU dialects, as gcc uses by
+    /// default, so that system macros such as `linux` and `unix` stay predefined.
+    pub fn gcc_name(&self) -> &'static str {
+        match self {
+            Standard::C89 => "gnu89",
+            Standard::C99 => "gnu99",
+            Standard::C11 => "gnu11",
+            Standard::C17 => "gnu17",
+            Standard::C23 => "gnu2x",
+        }
+    }
+}
+
 #[derive(Args,Debug)]
 #[group(required = false, multiple = false)]
 pub struct Command
@@ -0,0 +1,421 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::process::Output;
+use crate::span::Span;
+
+/// What went wrong, independently of how the message is worded. Codes are numbered by
+/// the stage that usually raises them (E01xx lexer, E02xx parser, E03xx semantic
+/// analysis, E05xx toolchain), except for the E00xx codes, which any stage may raise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+   /// A construct this compiler does not implement yet.
+   Unsupported,
+   /// A feature from a later standard than the one selected.
+   NotInStandard,
+   InvalidToken,
+   InvalidConstant,
+   InvalidLiteral,
+   UnterminatedComment,
+   UnsupportedDirective,
+   SyntaxError,
+   InvalidDeclaration,
+   Redeclaration,
+   Redefinition,
+   ConflictingTypes,
+   NotConstant,
+   InvalidArraySize,
+   InvalidAlignment,
+   InvalidInitializer,
+   StaticAssertionFailed,
+   /// An operand or conversion that the type rules do not allow.
+   InvalidOperands,
+   PreprocessorFailed,
+   AssemblerFailed,
+   Io,
+}
+
+impl ErrorCode {
+   pub fn number(&self) -> u16 {
+      match self {
+         ErrorCode::Unsupported => 1,
+         ErrorCode::NotInStandard => 2,
+         ErrorCode::InvalidToken => 101,
+         ErrorCode::InvalidConstant => 102,
+         ErrorCode::InvalidLiteral => 103,
+         ErrorCode::UnterminatedComment => 104,
+         ErrorCode::UnsupportedDirective => 105,
+         ErrorCode::SyntaxError => 201,
+         ErrorCode::InvalidDeclaration => 202,
+         ErrorCode::Redeclaration => 301,
+         ErrorCode::Redefinition => 302,
+         ErrorCode::ConflictingTypes => 303,
+         ErrorCode::NotConstant => 304,
+         ErrorCode::InvalidArraySize => 305,
+         ErrorCode::InvalidAlignment => 306,
+         ErrorCode::InvalidInitializer => 307,
+         ErrorCode::StaticAssertionFailed => 308,
+         ErrorCode::InvalidOperands => 309,
+         ErrorCode::PreprocessorFailed => 501,
+         ErrorCode::AssemblerFailed => 502,
+         ErrorCode::Io => 503,
+      }
+   }
+}
+
+impl fmt::Display for ErrorCode {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "E{:04}", self.number())
+   }
+}
+
+/// Extra information attached to an error, optionally pointing at another location.
+#[derive(Debug)]
+pub struct Note {
+   pub message: String,
+   pub span: Option<Span>,
+}
+
+/// A secondary location shown with the source of an error, underlined with dashes.
+#[derive(Debug)]
+pub struct Label {
+   pub span: Span,
+   pub message: String,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+   pub code: ErrorCode,
+   pub message: String,
+   pub span: Option<Span>,
+   pub labels: Vec<Label>,
+   pub notes: Vec<Note>,
+}
+
+/// An error that stops compilation, by the stage that raised it. Each stage exits the
+/// process with its own status so that scripts can tell the failures apart.
+#[derive(Debug)]
+pub enum CompileError {
+   Lexer(Box<Diagnostic>),
+   Parser(Box<Diagnostic>),
+   Semantic(Box<Diagnostic>),
+   Codegen(Box<Diagnostic>),
+   /// gcc, used for preprocessing and assembling, or the file system.
+   Toolchain(Box<Diagnostic>),
+}
+
+impl CompileError {
+   pub fn lexer(code: ErrorCode, message: impl Into<String>) -> Self {
+      CompileError::Lexer(Box::new(Diagnostic::new(code, message)))
+   }
+
+   pub fn parser(code: ErrorCode, message: impl Into<String>) -> Self {
+      CompileError::Parser(Box::new(Diagnostic::new(code, message)))
+   }
+
+   pub fn semantic(code: ErrorCode, message: impl Into<String>) -> Self {
+      CompileError::Semantic(Box::new(Diagnostic::new(code, message)))
+   }
+
+   pub fn codegen(code: ErrorCode, message: impl Into<String>) -> Self {
+      CompileError::Codegen(Box::new(Diagnostic::new(code, message)))
+   }
+
+   pub fn toolchain(code: ErrorCode, message: impl Into<String>) -> Self {
+      CompileError::Toolchain(Box::new(Diagnostic::new(code, message)))
+   }
+
+   pub fn diagnostic(&self) -> &Diagnostic {
+      match self {
+         CompileError::Lexer(d) | CompileError::Parser(d) | CompileError::Semantic(d)
+         | CompileError::Codegen(d) | CompileError::Toolchain(d) => d,
+      }
+   }
+
+   fn diagnostic_mut(&mut self) -> &mut Diagnostic {
+      match self {
+         CompileError::Lexer(d) | CompileError::Parser(d) | CompileError::Semantic(d)
+         | CompileError::Codegen(d) | CompileError::Toolchain(d) => d,
+      }
+   }
+
+   /// The process exit status for this error. 2 is left for command line errors, which
+   /// clap reports itself; a panic exits with Rust's 101.
+   pub fn exit_code(&self) -> u8 {
+      match self {
+         CompileError::Lexer(_) => 3,
+         CompileError::Parser(_) => 4,
+         CompileError::Semantic(_) => 5,
+         CompileError::Codegen(_) => 6,
+         CompileError::Toolchain(_) => 7,
+      }
+   }
+
+   /// Locates the error at `span`, unless a more precise location is already known.
+   pub fn at(mut self, span: &Span) -> Self {
+      let diagnostic = self.diagnostic_mut();
+      if diagnostic.span.is_none() {
+         diagnostic.span = Some(span.clone());
+      }
+      self
+   }
+
+   pub fn with_label(mut self, span: &Span, message: impl Into<String>) -> Self {
+      self.diagnostic_mut().labels.push(Label { span: span.clone(), message: message.into() });
+      self
+   }
+
+   pub fn with_note(mut self, message: impl Into<String>, span: Option<Span>) -> Self {
+      self.diagnostic_mut().notes.push(Note { message: message.into(), span });
+      self
+   }
+
+   /// A failed run of gcc, with whatever it printed as a note.
+   pub fn from_gcc(code: ErrorCode, what: &str, output: &Output) -> Self {
+      let error = CompileError::toolchain(code, format!("{} failed ({})", what, output.status));
+      let stderr = String::from_utf8_lossy(&output.stderr);
+      match stderr.trim() {
+         "" => error,
+         text => error.with_note(text, None),
+      }
+   }
+}
+
+impl Diagnostic {
+   pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+      Diagnostic { code, message: message.into(), span: None, labels: Vec::new(), notes: Vec::new() }
+   }
+}
+
+impl CompileError {
+   /// Formats the error the way gcc does: a `file:line:col: error:` line, the source
+   /// line with the span underlined and any labels below it, then each note, with its
+   /// own source line if it points somewhere. With `color`, the output uses gcc's ANSI
+   /// colors.
+   pub fn render(&self, color: bool) -> String {
+      self.render_with(color, &source_line)
+   }
+
+   /// Renders the error, looking up source lines with `source` rather than reading files.
+   fn render_with(&self, color: bool, source: &SourceLookup) -> String {
+      let diagnostic = self.diagnostic();
+      let style = Style { color };
+      let mut out = String::new();
+      let message = format!("{} [{}]", diagnostic.message, diagnostic.code);
+      let error = style.paint(Style::ERROR, "error:");
+      render_message(&mut out, &style, source, diagnostic.span.as_ref(), error, &message, &diagnostic.labels);
+      for note in &diagnostic.notes {
+         render_message(&mut out, &style, source, note.span.as_ref(), style.paint(Style::NOTE, "note:"), &note.message, &[]);
+      }
+      out
+   }
+}
+
+/// Optional ANSI escapes for diagnostics.
+struct Style {
+   color: bool,
+}
+
+impl Style {
+   const LOCUS: &'static str = "01";
+   const ERROR: &'static str = "01;31";
+   const NOTE: &'static str = "01;36";
+   const CARET: &'static str = "01;32";
+   const LABEL: &'static str = "01;34";
+
+   fn paint(&self, sgr: &str, text: &str) -> String {
+      if self.color {
+         format!("\x1b[{}m\x1b[K{}\x1b[m\x1b[K", sgr, text)
+      } else {
+         text.to_string()
+      }
+   }
+}
+
+/// Finds the source line a span starts on.
+type SourceLookup = dyn Fn(&Span) -> Option<String>;
+
+fn render_message(out: &mut String, style: &Style, source: &SourceLookup, span: Option<&Span>, severity: String, message: &str, labels: &[Label]) {
+   if let Some(span) = span {
+      out.push_str(&style.paint(Style::LOCUS, &format!("{}:", span)));
+      out.push(' ');
+   }
+   out.push_str(&format!("{} {}\n", severity, message));
+   if let Some(span) = span {
+      render_snippet(out, style, source, span, labels);
+   }
+}
+
+/// Shows the source line a span starts on, marking the span with a caret followed by
+/// tildes, then each label. A label on the same line is only marked below it; one
+/// elsewhere gets its own locus and line. Lines are left out if their file cannot be
+/// read, as for `<built-in>`, and marks if the span has no known column.
+fn render_snippet(out: &mut String, style: &Style, source: &SourceLookup, span: &Span, labels: &[Label]) {
+   let line = source(span);
+   if let Some(line) = &line {
+      out.push_str(&format!("{:>5} | {}\n", span.line, line));
+      if let Some((indent, width)) = underline(line, span) {
+         let marks = format!("^{}", "~".repeat(width - 1));
+         out.push_str(&format!("{:>5} | {}{}\n", "", indent, style.paint(Style::CARET, &marks)));
+      }
+   }
+   for label in labels {
+      let on_same_line = label.span.file == span.file && label.span.line == span.line && line.is_some();
+      let label_line = if on_same_line { line.clone() } else { source(&label.span) };
+      let Some(label_line) = label_line else {
+         out.push_str(&format!("  --> {} {}\n", style.paint(Style::LOCUS, &format!("{}:", label.span)), label.message));
+         continue;
+      };
+      if !on_same_line {
+         out.push_str(&format!("  --> {}\n", style.paint(Style::LOCUS, &label.span.to_string())));
+         out.push_str(&format!("{:>5} | {}\n", label.span.line, label_line));
+      }
+      match underline(&label_line, &label.span) {
+         Some((indent, width)) => {
+            let marks = style.paint(Style::LABEL, &"-".repeat(width));
+            out.push_str(&format!("{:>5} | {}{} {}\n", "", indent, marks, label.message));
+         },
+         None => out.push_str(&format!("{:>5} | {}\n", "", label.message)),
+      }
+   }
+}
+
+/// Line `span.line` of the file the span is in, without its line terminator.
+fn source_line(span: &Span) -> Option<String> {
+   let source = fs::read_to_string(&*span.file).ok()?;
+   let line = source.lines().nth(span.line - 1)?;
+   Some(line.trim_end_matches('\r').to_string())
+}
+
+/// The indentation that puts marks under the start of `span` in `line`, keeping tabs so
+/// that they line up, and how many characters to mark: up to the end of the span or of
+/// the line.
+fn underline(line: &str, span: &Span) -> Option<(String, usize)> {
+   let column = span.column?;
+   let line_end = line.chars().count() + 1;
+   let end = match span.end_column {
+      Some(end) if span.end_line == span.line => end.min(line_end),
+      _ => line_end,
+   };
+   let indent = line.chars().take(column - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+   Some((indent, end.saturating_sub(column).max(1)))
+}
+
+impl fmt::Display for CompileError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", self.render(false))
+   }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<io::Error> for CompileError {
+   fn from(error: io::Error) -> Self {
+      CompileError::toolchain(ErrorCode::Io, error.to_string())
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::env;
+
+   fn span(file: &str, line: usize, column: Option<usize>, end_column: Option<usize>) -> Span {
+      Span { file: file.into(), line, column, end_line: line, end_column, start: 0, end: 0 }
+   }
+
+   /// Source lines for `test.c`; every other file is unreadable.
+   fn lookup(span: &Span) -> Option<String> {
+      let lines = ["int main(void) {", "\treturn 1 +   x;", "}"];
+      match &*span.file {
+         "test.c" => lines.get(span.line - 1).map(|l| l.to_string()),
+         _ => None,
+      }
+   }
+
+   fn render(error: &CompileError) -> String {
+      error.render_with(false, &lookup)
+   }
+
+   #[test]
+   fn locus_and_snippet() {
+      let error = CompileError::semantic(ErrorCode::InvalidOperands, "Bad operand").at(&span("test.c", 2, Some(9), Some(16)));
+      assert_eq!(render(&error), concat!(
+         "test.c:2:9: error: Bad operand [E0309]\n",
+         "    2 | \treturn 1 +   x;\n",
+         "      | \t       ^~~~~~~\n",
+      ));
+   }
+
+   #[test]
+   fn marks_are_clamped_to_the_line() {
+      let line = "\treturn 1 +   x;";
+      let mark = |column, end_column| underline(line, &span("test.c", 2, column, end_column));
+      // Tabs are kept in the indent so the marks line up however wide a tab is.
+      assert_eq!(mark(Some(2), Some(8)), Some((String::from("\t"), 6)));
+      assert_eq!(mark(Some(9), Some(40)), Some((String::from("\t       "), 8)));
+      assert_eq!(mark(Some(16), None), Some((String::from("\t              "), 1)));
+      assert_eq!(mark(Some(17), Some(17)), Some((String::from("\t               "), 1)));
+      assert_eq!(mark(None, Some(4)), None);
+      // A span running onto later lines is marked to the end of its first line.
+      let multiline = Span { end_line: 3, end_column: Some(2), ..span("test.c", 2, Some(9), None) };
+      assert_eq!(underline(line, &multiline), Some((String::from("\t       "), 8)));
+   }
+
+   #[test]
+   fn spans_without_columns_or_source() {
+      let error = CompileError::lexer(ErrorCode::InvalidToken, "Invalid Token").at(&span("test.c", 2, None, None));
+      assert_eq!(render(&error), "test.c:2: error: Invalid Token [E0101]\n    2 | \treturn 1 +   x;\n");
+      let error = CompileError::parser(ErrorCode::SyntaxError, "Syntax Error").at(&span("<built-in>", 1, Some(1), Some(2)));
+      assert_eq!(render(&error), "<built-in>:1:1: error: Syntax Error [E0201]\n");
+      let error = CompileError::toolchain(ErrorCode::Io, "No such file");
+      assert_eq!(render(&error), "error: No such file [E0503]\n");
+   }
+
+   #[test]
+   fn labels_and_notes() {
+      let error = CompileError::semantic(ErrorCode::StaticAssertionFailed, "Static assertion failed")
+         .at(&span("test.c", 2, Some(2), Some(8)))
+         .with_label(&span("test.c", 2, Some(9), Some(14)), "this condition is false")
+         .with_label(&span("test.c", 1, Some(5), Some(9)), "declared here")
+         .with_label(&span("<built-in>", 3, Some(1), Some(2)), "from a macro")
+         .with_note("previous declaration was here", Some(span("test.c", 3, Some(1), Some(2))))
+         .with_note("no location", None);
+      assert_eq!(render(&error), concat!(
+         "test.c:2:2: error: Static assertion failed [E0308]\n",
+         "    2 | \treturn 1 +   x;\n",
+         "      | \t^~~~~~\n",
+         "      | \t       ----- this condition is false\n",
+         "  --> test.c:1:5\n",
+         "    1 | int main(void) {\n",
+         "      |     ---- declared here\n",
+         "  --> <built-in>:3:1: from a macro\n",
+         "test.c:3:1: note: previous declaration was here\n",
+         "    3 | }\n",
+         "      | ^\n",
+         "note: no location\n",
+      ));
+   }
+
+   #[test]
+   fn color() {
+      let error = CompileError::parser(ErrorCode::SyntaxError, "Syntax Error").at(&span("test.c", 3, Some(1), Some(2)));
+      assert_eq!(error.render_with(true, &lookup), concat!(
+         "\x1b[01m\x1b[Ktest.c:3:1:\x1b[m\x1b[K \x1b[01;31m\x1b[Kerror:\x1b[m\x1b[K Syntax Error [E0201]\n",
+         "    3 | }\n",
+         "      | \x1b[01;32m\x1b[K^\x1b[m\x1b[K\n",
+      ));
+      assert!(!render(&error).contains('\x1b'));
+   }
+
+   #[test]
+   fn source_lines_are_read_from_the_file() {
+      let path = env::temp_dir().join(format!("source_line_test_{}.c", std::process::id()));
+      fs::write(&path, "first\r\nsecond\n").expect("writes the source");
+      let file = path.to_str().expect("temporary path is UTF-8");
+      let lines: Vec<_> = (1..=3).map(|line| source_line(&span(file, line, None, None))).collect();
+      _ = fs::remove_file(&path);
+      assert_eq!(lines, [Some(String::from("first")), Some(String::from("second")), None]);
+      assert_eq!(source_line(&span("<built-in>", 1, None, None)), None);
+   }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+use crate::span::Span;
+use super::source_chars::Position;
+
+/// Maps physical lines of the lexed text onto lines of the original source files,
+/// following the line markers left by the preprocessor.
+pub struct LineMap<'a> {
+   text: &'a str,
+   file: Rc<str>,
+   /// Added to a physical line number to give the line in `file`.
+   line_offset: isize,
+   /// Set by the first line marker. The preprocessor collapses whitespace and expands
+   /// macros, so from then on columns in the text say nothing about the original line.
+   preprocessed: bool,
+   /// The lines of each original file, or None if it cannot be read, as for `<built-in>`.
+   sources: HashMap<Rc<str>, Option<Vec<String>>>,
+   /// How far tokens have been matched in the original line, as a byte offset, or None
+   /// once a token failed to match there.
+   cursor: Option<(Rc<str>, usize, Option<usize>)>,
+}
+
+impl<'a> LineMap<'a> {
+   pub fn new(text: &'a str, file: Rc<str>) -> Self {
+      LineMap { text, file, line_offset: 0, preprocessed: false, sources: HashMap::new(), cursor: None }
+   }
+
+   fn line(&self, position: Position) -> usize {
+      (position.line as isize + self.line_offset).max(1) as usize
+   }
+
+   /// Follows a `#line` directive or gcc line marker, given the text after its `#` and the
+   /// physical line it is on. Returns false if the directive is neither.
+   pub fn follow_marker(&mut self, directive: &str, physical_line: usize) -> bool {
+      let Some((line, file)) = parse_line_marker(directive) else {
+         return false;
+      };
+      // The marker gives the number of the line after it.
+      self.line_offset = line as isize - (physical_line as isize + 1);
+      self.preprocessed = true;
+      if let Some(file) = file {
+         self.file = Rc::from(file);
+      }
+      true
+   }
+
+   /// The span of the text between two positions, in terms of the original source.
+   pub fn span(&mut self, start: Position, end: Position) -> Span {
+      let line = self.line(start);
+      let end_line = self.line(end);
+      let (column, end_column) = if self.preprocessed {
+         let spelling = &self.text[start.offset..end.offset];
+         let column = self.anchor(line, spelling);
+         (column, column.map(|c| c + spelling.chars().count()))
+      } else {
+         (Some(start.column), Some(end.column).filter(|_| end.line == start.line))
+      };
+      Span {
+         file: self.file.clone(),
+         line,
+         column,
+         end_line,
+         end_column,
+         start: start.offset,
+         end: end.offset,
+      }
+   }
+
+   /// Finds the column of the next token, spelled `spelling`, on line `line` of the
+   /// original file. Tokens are matched in order, skipping whitespace and comments; once
+   /// one does not match, as when it came from a macro, the rest of the line has no
+   /// columns rather than wrong ones.
+   fn anchor(&mut self, line: usize, spelling: &str) -> Option<usize> {
+      let file = self.file.clone();
+      let source = self.sources.entry(file.clone()).or_insert_with(|| {
+         fs::read_to_string(&*file).ok().map(|s| s.lines().map(String::from).collect())
+      });
+      let text = source.as_ref()?.get(line - 1)?;
+      let from = match &self.cursor {
+         Some((f, l, from)) if *f == file && *l == line => *from,
+         _ => Some(0),
+      };
+      let found = from.and_then(|from| find_token(text, from, spelling));
+      self.cursor = Some((file, line, found.map(|at| at + spelling.len())));
+      found.map(|at| text[..at].chars().count() + 1)
+   }
+}
+
+/// The byte offset of `spelling` in `line` if it is the next token at or after `from`.
+fn find_token(line: &str, from: usize, spelling: &str) -> Option<usize> {
+   let is_word = |c: char| c.is_alphanumeric() || c == '_';
+   let mut rest = &line[from..];
+   loop {
+      rest = rest.trim_start();
+      if let Some(after) = rest.strip_prefix(spelling) {
+         // `x` is not the start of `xy`, which a macro may have expanded to it.
+         let splits_word = spelling.ends_with(is_word) && after.starts_with(is_word);
+         return (!splits_word && !spelling.is_empty()).then_some(line.len() - rest.len());
+      }
+      rest = match rest.strip_prefix("/*") {
+         Some(comment) => &comment[comment.find("*/")? + 2..],
+         None => return None,
+      };
+   }
+}
+
+/// Parses the text after the `#` of a `#line` directive or a gcc line marker such as
+/// `# 12 "file.c" 2`, giving the line number of the next line and any new file name.
+fn parse_line_marker(directive: &str) -> Option<(usize, Option<String>)> {
+   let rest = directive.trim_start();
+   let rest = rest.strip_prefix("line").unwrap_or(rest).trim_start();
+   let digits_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+   let line = rest[..digits_len].parse().ok()?;
+   let rest = rest[digits_len..].trim_start();
+   if rest.is_empty() {
+      return Some((line, None));
+   }
+
+   let mut chars = rest.strip_prefix('"')?.chars();
+   let mut file = String::new();
+   loop {
+      match chars.next()? {
+         '"' => break,
+         '\\' => file.push(chars.next()?),
+         c => file.push(c),
+      }
+   }
+   Some((line, Some(file)))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   /// A line map whose files are given here rather than read from disk.
+   fn line_map<'a>(text: &'a str, files: &[(&str, Option<&[&str]>)]) -> LineMap<'a> {
+      let mut lines = LineMap::new(text, Rc::from("test.c"));
+      for (name, source) in files {
+         let source = source.map(|lines| lines.iter().map(|l| l.to_string()).collect());
+         lines.sources.insert(Rc::from(*name), source);
+      }
+      lines
+   }
+
+   fn position(offset: usize, line: usize, column: usize) -> Position {
+      Position { offset, line, column }
+   }
+
+   #[test]
+   fn line_markers() {
+      assert_eq!(parse_line_marker(r#" 12 "file.c" 2"#), Some((12, Some(String::from("file.c")))));
+      assert_eq!(parse_line_marker(r#" 1 "<built-in>""#), Some((1, Some(String::from("<built-in>")))));
+      assert_eq!(parse_line_marker("line 7"), Some((7, None)));
+      assert_eq!(parse_line_marker(r#"line 7 "dir\\a \"b\".c""#), Some((7, Some(String::from(r#"dir\a "b".c"#)))));
+      assert_eq!(parse_line_marker("pragma once"), None);
+      assert_eq!(parse_line_marker("line"), None);
+      assert_eq!(parse_line_marker(r#" 3 "unterminated"#), None);
+      assert_eq!(parse_line_marker(" 3 file.c"), None);
+   }
+
+   #[test]
+   fn finding_tokens() {
+      assert_eq!(find_token("  return 0;", 0, "return"), Some(2));
+      assert_eq!(find_token("  return 0;", 8, "0"), Some(9));
+      assert_eq!(find_token("a+b", 1, "+"), Some(1));
+      assert_eq!(find_token("/* c */ x /* d */ y", 0, "x"), Some(8));
+      assert_eq!(find_token("/* c */ x /* d */ y", 9, "y"), Some(18));
+      // A macro expanded to `x` does not match the start of `xy`, nor anything further on.
+      assert_eq!(find_token("xy x", 0, "x"), None);
+      assert_eq!(find_token("FOO;", 0, ";"), None);
+      assert_eq!(find_token("x+", 0, "x"), Some(0));
+      assert_eq!(find_token("/* open", 0, "x"), None);
+      assert_eq!(find_token("x", 0, ""), None);
+   }
+
+   #[test]
+   fn anchoring_tokens_in_the_original_line() {
+      let mut lines = line_map("", &[("a.c", Some(&["int  x =   FOO ;", "/* \u{e9} */ y"]))]);
+      lines.file = Rc::from("a.c");
+      assert_eq!(lines.anchor(1, "int"), Some(1));
+      assert_eq!(lines.anchor(1, "x"), Some(6));
+      assert_eq!(lines.anchor(1, "="), Some(8));
+      // `FOO` was expanded by the preprocessor: the rest of the line has no columns.
+      assert_eq!(lines.anchor(1, "42"), None);
+      assert_eq!(lines.anchor(1, ";"), None);
+      // Columns count characters, not bytes.
+      assert_eq!(lines.anchor(2, "y"), Some(9));
+      // Going back to a line starts matching from its beginning again.
+      assert_eq!(lines.anchor(1, "int"), Some(1));
+      assert_eq!(lines.anchor(3, "int"), None);
+   }
+
+   #[test]
+   fn unreadable_files_have_no_columns() {
+      let mut lines = line_map("", &[("<built-in>", None)]);
+      lines.file = Rc::from("<built-in>");
+      assert_eq!(lines.anchor(1, "int"), None);
+   }
+
+   #[test]
+   fn spans_follow_line_markers() {
+      let text = "# 10 \"a.c\"\nint x;\n";
+      let mut source = vec![""; 9];
+      source.push("int   x;");
+      let mut lines = line_map(text, &[("a.c", Some(&source))]);
+      // Before any marker, columns are taken from the text itself.
+      let span = lines.span(position(0, 1, 1), position(1, 1, 2));
+      assert_eq!((&*span.file, span.line, span.column, span.end_column), ("test.c", 1, Some(1), Some(2)));
+
+      assert!(lines.follow_marker(" 10 \"a.c\"", 1));
+      assert!(!lines.follow_marker("define X", 1));
+      let span = lines.span(position(11, 2, 1), position(14, 2, 4));
+      assert_eq!((&*span.file, span.line, span.column, span.end_column), ("a.c", 10, Some(1), Some(4)));
+      let span = lines.span(position(15, 2, 5), position(16, 2, 6));
+      assert_eq!((span.line, span.column, span.end_column), (10, Some(7), Some(8)));
+      assert_eq!((span.start, span.end), (15, 16));
+   }
+}
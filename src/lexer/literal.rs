@@ -0,0 +1,224 @@
+use super::source_chars::SourceChars;
+use super::token::{Constant, Encoding, IntType, StringChar};
+
+/// Reads the characters of a character constant or string literal up to and including
+/// the closing `quote`, decoding escape sequences.
+pub fn read_chars(chars: &mut SourceChars, quote: char, encoding: Encoding) -> Result<Vec<StringChar>, String> {
+   let mut result: Vec<StringChar> = Vec::new();
+   loop {
+      match chars.next() {
+         Some(c) if c == quote => break,
+         Some('\\') => result.push(read_escape(chars, encoding)?),
+         Some('\n') | None if quote == '"' => return Err(String::from("Unterminated string literal")),
+         Some('\n') | None => return Err(String::from("Unterminated character constant")),
+         Some(c) => result.push(StringChar::CodePoint(c)),
+      }
+   }
+   Ok(result)
+}
+
+/// Decodes an escape sequence after its backslash (C17 6.4.4.4).
+fn read_escape(chars: &mut SourceChars, encoding: Encoding) -> Result<StringChar, String> {
+   let c = chars.next().ok_or_else(|| String::from("Unterminated escape sequence"))?;
+   let simple = match c {
+      '\'' | '"' | '?' | '\\' => c,
+      'a' => '\x07',
+      'b' => '\x08',
+      'f' => '\x0c',
+      'n' => '\n',
+      'r' => '\r',
+      't' => '\t',
+      'v' => '\x0b',
+      'u' | 'U' => return Ok(StringChar::CodePoint(read_universal_character(chars, c)?)),
+      '0'..='7' => {
+         let mut value = c.to_digit(8).unwrap_or_default();
+         for _ in 0..2 {
+            match chars.next_if(|d| d.is_digit(8)) {
+               Some(d) => value = value * 8 + d.to_digit(8).unwrap_or_default(),
+               None => break,
+            }
+         }
+         return code_unit(value as u64, encoding);
+      },
+      'x' => {
+         let mut value: u64 = 0;
+         let mut has_digits = false;
+         while let Some(d) = chars.next_if(|d| d.is_ascii_hexdigit()) {
+            value = value.saturating_mul(16).saturating_add(d.to_digit(16).unwrap_or_default() as u64);
+            has_digits = true;
+         }
+         if !has_digits {
+            return Err(String::from("\\x used with no following hex digits"));
+         }
+         return code_unit(value, encoding);
+      },
+      _ => return Err(format!("Unknown escape sequence '\\{}'", c)),
+   };
+   Ok(StringChar::CodePoint(simple))
+}
+
+/// An octal or hex escape gives a code unit directly, which must fit the element type.
+fn code_unit(value: u64, encoding: Encoding) -> Result<StringChar, String> {
+   if value > encoding.max_unit() {
+      return Err(String::from("Escape sequence out of range"));
+   }
+   Ok(StringChar::Unit(value as u32))
+}
+
+/// Decodes the hex digits of a `\u` or `\U` universal character name (C17 6.4.3).
+pub fn read_universal_character(chars: &mut SourceChars, kind: char) -> Result<char, String> {
+   let digits = if kind == 'u' { 4 } else { 8 };
+   let mut value: u32 = 0;
+   for _ in 0..digits {
+      match chars.next_if(|d| d.is_ascii_hexdigit()) {
+         Some(d) => value = value * 16 + d.to_digit(16).unwrap_or_default(),
+         None => return Err(format!("Incomplete universal character name \\{}", kind)),
+      }
+   }
+   // Only $, @ and ` may be named below U+00A0; surrogates are not characters.
+   let is_basic = value < 0xA0 && !matches!(value, 0x24 | 0x40 | 0x60);
+   match char::from_u32(value) {
+      Some(c) if !is_basic => Ok(c),
+      _ => Err(format!("Invalid universal character name U+{:04X}", value)),
+   }
+}
+
+/// Encodes decoded characters into the code units of the literal's element type. Source
+/// characters and UCNs are encoded; escape-given units are kept as they are.
+pub fn encode(encoding: Encoding, chars: &[StringChar]) -> Vec<u32> {
+   let mut units: Vec<u32> = Vec::new();
+   for c in chars {
+      match (c, encoding) {
+         (StringChar::Unit(u), _) => units.push(*u),
+         (StringChar::CodePoint(c), Encoding::Plain | Encoding::Utf8) => {
+            units.extend(c.to_string().bytes().map(u32::from));
+         },
+         (StringChar::CodePoint(c), Encoding::Utf16) => {
+            units.extend(c.encode_utf16(&mut [0; 2]).iter().map(|u| u32::from(*u)));
+         },
+         (StringChar::CodePoint(c), Encoding::Wide | Encoding::Utf32) => units.push(*c as u32),
+      }
+   }
+   units
+}
+
+/// The value and type of a character constant (C17 6.4.4.4p10-11). Values are stored as
+/// the 64-bit two's complement pattern of the constant's type.
+pub fn char_constant(encoding: Encoding, chars: &[StringChar]) -> Result<Constant, String> {
+   if chars.is_empty() {
+      return Err(String::from("Empty character constant"));
+   }
+   let units = encode(encoding, chars);
+   match encoding {
+      // Plain char is signed. Multi-character constants pack their bytes as gcc does.
+      Encoding::Plain => {
+         let value = match units.as_slice() {
+            [unit] => *unit as u8 as i8 as i64,
+            _ if units.len() <= 4 => units.iter().fold(0u32, |v, unit| (v << 8) | unit) as i32 as i64,
+            _ => return Err(String::from("Character constant too long for its type")),
+         };
+         Ok(Constant::Integer(value as u64, IntType::Int))
+      },
+      _ if chars.len() > 1 => Err(String::from("Wide character constant must contain exactly one character")),
+      Encoding::Utf16 if units.len() > 1 => {
+         Err(String::from("Character in char16_t constant needs more than one code unit"))
+      },
+      Encoding::Utf16 => Ok(Constant::Integer(units[0] as u64, IntType::UShort)),
+      Encoding::Utf32 => Ok(Constant::Integer(units[0] as u64, IntType::UInt)),
+      // wchar_t is int on x86-64 Linux.
+      Encoding::Wide => Ok(Constant::Integer(units[0] as i32 as i64 as u64, IntType::Int)),
+      Encoding::Utf8 => Err(String::from("UTF-8 character constants are not supported")),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use StringChar::{CodePoint, Unit};
+
+   /// Reads the body of a string literal, given without its opening quote.
+   fn read(text: &str, encoding: Encoding) -> Result<Vec<StringChar>, String> {
+      read_chars(&mut SourceChars::new(text), '"', encoding)
+   }
+
+   fn constant(encoding: Encoding, body: &str) -> Result<Constant, String> {
+      let chars = read_chars(&mut SourceChars::new(&format!("{}'", body)), '\'', encoding)?;
+      char_constant(encoding, &chars)
+   }
+
+   #[test]
+   fn simple_escapes() {
+      let expected: Vec<StringChar> = "\x07\x08\x0c\n\r\t\x0b'\"?\\".chars().map(CodePoint).collect();
+      assert_eq!(read(r#"\a\b\f\n\r\t\v\'\"\?\\""#, Encoding::Plain), Ok(expected));
+      assert!(read(r#"\q""#, Encoding::Plain).is_err());
+      assert!(read(r#"\"#, Encoding::Plain).is_err());
+      assert!(read("abc", Encoding::Plain).is_err());
+      assert!(read("ab\nc\"", Encoding::Plain).is_err());
+   }
+
+   #[test]
+   fn numeric_escapes() {
+      assert_eq!(read(r#"\101\0\1234""#, Encoding::Plain), Ok(vec![Unit(0o101), Unit(0), Unit(0o123), CodePoint('4')]));
+      assert_eq!(read(r#"\x41\x0g""#, Encoding::Plain), Ok(vec![Unit(0x41), Unit(0), CodePoint('g')]));
+      assert!(read(r#"\xg""#, Encoding::Plain).is_err());
+      // The value must fit the element type of the literal.
+      assert!(read(r#"\400""#, Encoding::Plain).is_err());
+      assert!(read(r#"\x100""#, Encoding::Utf8).is_err());
+      assert_eq!(read(r#"\x100""#, Encoding::Wide), Ok(vec![Unit(0x100)]));
+      assert_eq!(read(r#"\xFFFF""#, Encoding::Utf16), Ok(vec![Unit(0xFFFF)]));
+      assert!(read(r#"\x10000""#, Encoding::Utf16).is_err());
+      assert!(read(r#"\x100000000""#, Encoding::Utf32).is_err());
+      assert!(read(r#"\x00000000000000000000041""#, Encoding::Plain).is_ok());
+   }
+
+   #[test]
+   fn universal_character_names() {
+      assert_eq!(read(r#"\u00E9\U0001F600""#, Encoding::Plain), Ok(vec![CodePoint('\u{e9}'), CodePoint('\u{1F600}')]));
+      assert_eq!(read(r#"\u0024\u0040\u0060\u00A0""#, Encoding::Plain),
+         Ok(vec![CodePoint('$'), CodePoint('@'), CodePoint('`'), CodePoint('\u{A0}')]));
+      // Basic characters below U+00A0 may not be named, nor may surrogates.
+      assert!(read(r#"\u0041""#, Encoding::Plain).is_err());
+      assert!(read(r#"\u009F""#, Encoding::Plain).is_err());
+      assert!(read(r#"\uD800""#, Encoding::Plain).is_err());
+      assert!(read(r#"\UDFFF""#, Encoding::Plain).is_err());
+      assert!(read(r#"\U00110000""#, Encoding::Plain).is_err());
+      assert!(read(r#"\u12""#, Encoding::Plain).is_err());
+      assert!(read(r#"\U0001F60""#, Encoding::Plain).is_err());
+   }
+
+   #[test]
+   fn encoding_into_code_units() {
+      let chars = [CodePoint('a'), CodePoint('\u{e9}'), CodePoint('\u{1F600}'), Unit(0xFF)];
+      assert_eq!(encode(Encoding::Plain, &chars), [0x61, 0xC3, 0xA9, 0xF0, 0x9F, 0x98, 0x80, 0xFF]);
+      assert_eq!(encode(Encoding::Utf8, &chars), encode(Encoding::Plain, &chars));
+      assert_eq!(encode(Encoding::Utf16, &chars), [0x61, 0xE9, 0xD83D, 0xDE00, 0xFF]);
+      assert_eq!(encode(Encoding::Utf32, &chars), [0x61, 0xE9, 0x1F600, 0xFF]);
+      assert_eq!(encode(Encoding::Wide, &chars), [0x61, 0xE9, 0x1F600, 0xFF]);
+   }
+
+   #[test]
+   fn plain_character_constants() {
+      assert_eq!(constant(Encoding::Plain, "a"), Ok(Constant::Integer(97, IntType::Int)));
+      // Plain char is signed, so a high byte gives a negative int.
+      assert_eq!(constant(Encoding::Plain, r"\xff"), Ok(Constant::Integer(-1i64 as u64, IntType::Int)));
+      assert_eq!(constant(Encoding::Plain, "ab"), Ok(Constant::Integer(0x6162, IntType::Int)));
+      assert_eq!(constant(Encoding::Plain, "\u{e9}"), Ok(Constant::Integer(0xC3A9, IntType::Int)));
+      assert!(constant(Encoding::Plain, "abcde").is_err());
+      assert!(constant(Encoding::Plain, "").is_err());
+   }
+
+   #[test]
+   fn prefixed_character_constants() {
+      assert_eq!(constant(Encoding::Wide, "\u{e9}"), Ok(Constant::Integer(0xE9, IntType::Int)));
+      assert_eq!(constant(Encoding::Wide, r"\xffffffff"), Ok(Constant::Integer(-1i64 as u64, IntType::Int)));
+      assert_eq!(constant(Encoding::Utf16, "\u{e9}"), Ok(Constant::Integer(0xE9, IntType::UShort)));
+      assert_eq!(constant(Encoding::Utf16, r"\xffff"), Ok(Constant::Integer(0xFFFF, IntType::UShort)));
+      assert!(constant(Encoding::Utf16, "\u{1F600}").is_err());
+      assert_eq!(constant(Encoding::Utf32, "\u{1F600}"), Ok(Constant::Integer(0x1F600, IntType::UInt)));
+      for encoding in [Encoding::Wide, Encoding::Utf16, Encoding::Utf32] {
+         assert!(constant(encoding, "ab").is_err());
+         assert!(constant(encoding, "").is_err());
+      }
+      assert!(constant(Encoding::Utf8, "a").is_err());
+   }
+}
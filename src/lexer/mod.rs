@@ -1,79 +1,428 @@
+mod line_map;
+mod literal;
+mod number;
+mod source_chars;
 pub mod token;
 
-use token::Token;
+use std::rc::Rc;
+use crate::cli::Standard;
+use crate::error::{CompileError, ErrorCode};
+use line_map::LineMap;
+use source_chars::SourceChars;
+use token::{Constant, Encoding, IntType, Token, TokenKind, Trivia};
 
 pub struct Lexer {
-   pp_source: String
+   source: String,
+   file: Rc<str>,
+   std: Standard
+}
+
+fn invalid_literal(message: String) -> CompileError {
+   CompileError::lexer(ErrorCode::InvalidLiteral, message)
 }
 
 impl Lexer {
-   pub fn new(pp_source_: String) -> Self {
+   pub fn new(source_: String, file_: &str, std_: Standard) -> Self {
       Self {
-         pp_source: pp_source_
+         source: source_,
+         file: Rc::from(file_),
+         std: std_
       }
    }
 
-   pub fn lex(&mut self) -> Result<Vec<Token>, String> {
+   /// Keywords added in C23, which are ordinary identifiers in earlier modes.
+   fn c23_keyword(name: &str) -> Option<TokenKind> {
+      let kind = match name {
+         "bool" => TokenKind::Bool,
+         "true" => TokenKind::Constant(Constant::Integer(1, IntType::Bool)),
+         "false" => TokenKind::Constant(Constant::Integer(0, IntType::Bool)),
+         "nullptr" => TokenKind::Nullptr,
+         "typeof" => TokenKind::Typeof,
+         "typeof_unqual" => TokenKind::TypeofUnqual,
+         "constexpr" => TokenKind::Constexpr,
+         "static_assert" => TokenKind::StaticAssert,
+         "alignas" => TokenKind::Alignas,
+         "alignof" => TokenKind::Alignof,
+         _ => return None,
+      };
+      Some(kind)
+   }
+
+   pub fn lex(&mut self) -> Result<Vec<Token>, CompileError> {
       let mut tokens: Vec<Token> = Vec::new();
-      let mut chars = self.pp_source.chars().peekable();
-      while let Some(c) = chars.next() {
+      let mut chars = SourceChars::new(&self.source);
+      let mut lines = LineMap::new(&self.source, self.file.clone());
+      let mut pending_trivia: Vec<Trivia> = Vec::new();
+      let mut on_token_line = false;
+      let mut at_line_start = true;
+      loop {
+         let start = chars.position();
+         let Some(c) = chars.next() else {
+            break;
+         };
          match c {
-            '(' => tokens.push(Token::OpenParen),
-            ')' => tokens.push(Token::CloseParen),
-            ';' => tokens.push(Token::Semicolon),
-            '{' => tokens.push(Token::OpenBrace),
-            '}' => tokens.push(Token::CloseBrace),
-            '~' => tokens.push(Token::BitFlip),
-            '+' => tokens.push(Token::Plus),
-            '*' => tokens.push(Token::Asterisk),
-            '/' => tokens.push(Token::ForwardSlash),
-            '%' => tokens.push(Token::Percent),
-            '-' => {
-               if matches!(chars.peek(), Some('-')) {
-                  chars.next();
-                  tokens.push(Token::Decrement);
-               } else {
-                  tokens.push(Token::Negate);
+            '\n' => {
+               on_token_line = false;
+               at_line_start = true;
+               continue;
+            },
+            _ if c.is_whitespace() => continue,
+            '#' if at_line_start => {
+               let mut directive = String::new();
+               while let Some(t) = chars.next_if(|t| *t != '\n') {
+                  directive.push(t);
                }
-            }
-            _ if c.is_alphabetic() || c == '_' => {
-               let mut token_value: String = String::from(c);
-               while let Some(t) = chars.peek() {
-                  if t.is_alphanumeric() || *t == '_' {
-                     token_value.push(chars.next().unwrap());
-                  } else {
-                     match token_value.as_str() {
-                        "int" => tokens.push(Token::Int),
-                        "void" => tokens.push(Token::Void),
-                        "return" => tokens.push(Token::Return),
-                        _ => tokens.push(Token::Identifier(token_value))
+               if !lines.follow_marker(&directive, start.line) {
+                  let message = format!("Unsupported preprocessing directive \"#{}\"", directive.trim());
+                  return Err(CompileError::lexer(ErrorCode::UnsupportedDirective, message).at(&lines.span(start, chars.position())));
+               }
+               continue;
+            },
+            '/' if matches!(chars.peek(), Some('/' | '*')) => {
+               let mut text = String::new();
+               let comment = if chars.next_if_eq(&'/').is_some() {
+                  if self.std < Standard::C99 {
+                     let error = CompileError::lexer(ErrorCode::NotInStandard, "\"//\" comments are a C99 feature");
+                     return Err(error.at(&lines.span(start, chars.position())));
+                  }
+                  while let Some(t) = chars.next_if(|t| *t != '\n') {
+                     text.push(t);
+                  }
+                  Trivia::LineComment(text)
+               } else {
+                  chars.next();
+                  loop {
+                     match chars.next() {
+                        Some('*') if chars.next_if_eq(&'/').is_some() => break,
+                        Some(t) => text.push(t),
+                        None => {
+                           let error = CompileError::lexer(ErrorCode::UnterminatedComment, "Unterminated comment");
+                           return Err(error.at(&lines.span(start, chars.position())));
+                        },
                      }
-                     break;
                   }
+                  Trivia::BlockComment(text)
+               };
+               // A comment on the same line as the previous token trails it; anything
+               // else leads the next token.
+               match tokens.last_mut() {
+                  Some(token) if on_token_line => token.trailing_trivia.push(comment),
+                  _ => pending_trivia.push(comment),
                }
+               continue;
             },
-            _ if c.is_ascii_digit() => {
-               let mut token_value: String = String::from(c);
-               while let Some(t) = chars.peek() {
-                  if t.is_ascii_digit() {
-                     token_value.push(chars.next().unwrap());
-                  } else {
-                     break;
+            _ => (),
+         }
+
+         at_line_start = false;
+         let kind = self.lex_token(c, &mut chars).map_err(|e| e.at(&lines.span(start, chars.position())))?;
+         tokens.push(Token::new(kind, lines.span(start, chars.position()), std::mem::take(&mut pending_trivia)));
+         on_token_line = true;
+      }
+
+      // Comments after the last token have nothing left to lead, so keep them on the last one.
+      if let Some(token) = tokens.last_mut() {
+         token.trailing_trivia.append(&mut pending_trivia);
+      }
+      Ok(tokens)
+   }
+
+   /// Reads the rest of the token that starts with `c`.
+   fn lex_token(&self, c: char, chars: &mut SourceChars) -> Result<TokenKind, CompileError> {
+      let kind = match c {
+         '(' => TokenKind::OpenParen,
+         ')' => TokenKind::CloseParen,
+         ';' => TokenKind::Semicolon,
+         '{' => TokenKind::OpenBrace,
+         '}' => TokenKind::CloseBrace,
+         '[' => TokenKind::OpenBracket,
+         ']' => TokenKind::CloseBracket,
+         ',' => TokenKind::Comma,
+         '=' if chars.next_if_eq(&'=').is_some() => TokenKind::Equal,
+         '=' => TokenKind::Assign,
+         '~' => TokenKind::BitFlip,
+         '+' if chars.next_if_eq(&'+').is_some() => TokenKind::Increment,
+         '+' if chars.next_if_eq(&'=').is_some() => TokenKind::PlusAssign,
+         '+' => TokenKind::Plus,
+         '*' => TokenKind::Asterisk,
+         '%' => TokenKind::Percent,
+         '^' => TokenKind::Caret,
+         '?' => TokenKind::Question,
+         ':' => TokenKind::Colon,
+         '!' if chars.next_if_eq(&'=').is_some() => TokenKind::NotEqual,
+         '!' => TokenKind::LogicalNot,
+         '&' if chars.next_if_eq(&'&').is_some() => TokenKind::LogicalAnd,
+         '&' => TokenKind::Ampersand,
+         '|' if chars.next_if_eq(&'|').is_some() => TokenKind::LogicalOr,
+         '|' => TokenKind::Pipe,
+         '<' if chars.next_if_eq(&'<').is_some() => TokenKind::ShiftLeft,
+         '<' if chars.next_if_eq(&'=').is_some() => TokenKind::LessEqual,
+         '<' => TokenKind::LessThan,
+         '>' if chars.next_if_eq(&'>').is_some() => TokenKind::ShiftRight,
+         '>' if chars.next_if_eq(&'=').is_some() => TokenKind::GreaterEqual,
+         '>' => TokenKind::GreaterThan,
+         '"' => TokenKind::StringLiteral(Encoding::Plain, literal::read_chars(chars, '"', Encoding::Plain).map_err(invalid_literal)?),
+         '\'' => {
+            let value = literal::read_chars(chars, '\'', Encoding::Plain).map_err(invalid_literal)?;
+            TokenKind::Constant(literal::char_constant(Encoding::Plain, &value).map_err(invalid_literal)?)
+         },
+         '/' => TokenKind::ForwardSlash,
+         '-' => {
+            if matches!(chars.peek(), Some('-')) {
+               chars.next();
+               TokenKind::Decrement
+            } else {
+               TokenKind::Negate
+            }
+         }
+         _ if c.is_alphabetic() || c == '_' || c == '\\' => {
+            let mut token_value: String = String::new();
+            let mut next = Some(c);
+            while let Some(t) = next {
+               if t == '\\' {
+                  // Universal character names spell the same identifier as the characters they name.
+                  let Some(kind) = chars.next_if(|k| *k == 'u' || *k == 'U') else {
+                     return Err(CompileError::lexer(ErrorCode::InvalidToken, "Invalid Token"));
+                  };
+                  let ucn = literal::read_universal_character(chars, kind)
+                     .map_err(|e| CompileError::lexer(ErrorCode::InvalidToken, e))?;
+                  if !ucn.is_alphanumeric() || (token_value.is_empty() && ucn.is_numeric()) {
+                     let message = format!("Universal character name U+{:04X} is not allowed in an identifier", ucn as u32);
+                     return Err(CompileError::lexer(ErrorCode::InvalidToken, message));
                   }
+                  token_value.push(ucn);
+               } else {
+                  token_value.push(t);
                }
-               if let Some(t) = chars.peek() {
-                  if t.is_alphabetic() {
-                     return Err(String::from("Invalid identifier"));
-                  }
+               next = chars.next_if(|t| t.is_alphanumeric() || *t == '_' || *t == '\\');
+            }
+
+            // The Unicode prefixes are C11; before that `u"x"` is an identifier and a string.
+            let encoding = match token_value.as_str() {
+               "L" => Some(Encoding::Wide),
+               "u" if self.std >= Standard::C11 => Some(Encoding::Utf16),
+               "U" if self.std >= Standard::C11 => Some(Encoding::Utf32),
+               "u8" if self.std >= Standard::C11 => Some(Encoding::Utf8),
+               _ => None,
+            };
+            match (encoding, chars.peek()) {
+               (Some(encoding), Some('"')) => {
+                  chars.next();
+                  TokenKind::StringLiteral(encoding, literal::read_chars(chars, '"', encoding).map_err(invalid_literal)?)
+               },
+               // u8 character constants are a C23 addition, and not implemented yet.
+               (Some(Encoding::Utf8), Some('\'')) if self.std >= Standard::C23 => {
+                  return Err(CompileError::lexer(ErrorCode::Unsupported, "UTF-8 character constants are not supported"));
+               },
+               (Some(encoding), Some('\'')) if encoding != Encoding::Utf8 => {
+                  chars.next();
+                  let value = literal::read_chars(chars, '\'', encoding).map_err(invalid_literal)?;
+                  TokenKind::Constant(literal::char_constant(encoding, &value).map_err(invalid_literal)?)
+               },
+               _ => match token_value.as_str() {
+                  "int" => TokenKind::Int,
+                  "void" => TokenKind::Void,
+                  "char" => TokenKind::Char,
+                  "short" => TokenKind::Short,
+                  "long" => TokenKind::Long,
+                  "signed" => TokenKind::Signed,
+                  "unsigned" => TokenKind::Unsigned,
+                  "float" => TokenKind::Float,
+                  "double" => TokenKind::Double,
+                  "_Bool" => TokenKind::Bool,
+                  "typedef" => TokenKind::Typedef,
+                  "extern" => TokenKind::Extern,
+                  "static" => TokenKind::Static,
+                  "auto" => TokenKind::Auto,
+                  "register" => TokenKind::Register,
+                  "const" => TokenKind::Const,
+                  "volatile" => TokenKind::Volatile,
+                  "restrict" => TokenKind::Restrict,
+                  "inline" => TokenKind::Inline,
+                  "_Noreturn" => TokenKind::Noreturn,
+                  "return" => TokenKind::Return,
+                  "default" => TokenKind::Default,
+                  "sizeof" => TokenKind::Sizeof,
+                  name @ ("_Static_assert" | "_Alignas" | "_Alignof" | "_Generic") if self.std < Standard::C11 => {
+                     return Err(CompileError::lexer(ErrorCode::NotInStandard, format!("\"{}\" is a C11 feature", name)));
+                  },
+                  "_Static_assert" => TokenKind::StaticAssert,
+                  "_Alignas" => TokenKind::Alignas,
+                  "_Alignof" => TokenKind::Alignof,
+                  "_Generic" => TokenKind::Generic,
+                  // The spelling gcc's headers use for typeof in every mode.
+                  "__typeof__" => TokenKind::Typeof,
+                  name => match Self::c23_keyword(name) {
+                     Some(kind) if self.std >= Standard::C23 => kind,
+                     _ => TokenKind::Identifier(token_value),
+                  },
+               },
+            }
+         },
+         _ if c.is_ascii_digit() || (c == '.' && matches!(chars.peek(), Some(d) if d.is_ascii_digit())) => {
+            // Collect a whole preprocessing number so that suffixes and malformed
+            // constants like "123abc" are diagnosed rather than split into tokens.
+            let mut token_value: String = String::from(c);
+            while let Some(t) = chars.peek() {
+               let is_exponent_sign = (t == '+' || t == '-')
+                  && matches!(token_value.chars().last(), Some('e' | 'E' | 'p' | 'P'));
+               // A C23 digit separator continues the number only if a digit or letter follows it.
+               let is_separator = t == '\'' && {
+                  let mut lookahead = chars.clone();
+                  lookahead.next();
+                  matches!(lookahead.next(), Some(d) if d.is_ascii_alphanumeric() || d == '_')
+               };
+               if is_separator && self.std < Standard::C23 {
+                  let message = format!("Digit separator in \"{}'...\" is a C23 feature", token_value);
+                  return Err(CompileError::lexer(ErrorCode::NotInStandard, message));
+               }
+               if t.is_ascii_alphanumeric() || t == '_' || t == '.' || is_exponent_sign || is_separator {
+                  token_value.push(t);
+                  chars.next();
+               } else {
+                  break;
                }
-               tokens.push(Token::Integer(token_value.parse::<u64>().unwrap()));
-            },
-            _ if c.is_whitespace() => (),
-            _ => {
-               return Err(String::from("Invalid Token"));
             }
+            let constant = number::parse(&token_value, self.std)
+               .map_err(|e| CompileError::lexer(ErrorCode::InvalidConstant, e))?;
+            TokenKind::Constant(constant)
+         },
+         '.' if matches!(chars.peek(), Some('.')) => {
+            chars.next();
+            if chars.next_if_eq(&'.').is_none() {
+               return Err(CompileError::lexer(ErrorCode::InvalidToken, "Invalid Token"));
+            }
+            TokenKind::Ellipsis
+         },
+         _ => {
+            return Err(CompileError::lexer(ErrorCode::InvalidToken, "Invalid Token"));
          }
+      };
+      Ok(kind)
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn lex(source: &str) -> Result<Vec<Token>, CompileError> {
+      Lexer::new(source.to_string(), "test.c", Standard::C23).lex()
+   }
+
+   fn lex_as(source: &str, std: Standard) -> Result<Vec<Token>, CompileError> {
+      Lexer::new(source.to_string(), "test.c", std).lex()
+   }
+
+   fn trivia(trivia: &[Trivia]) -> Vec<String> {
+      trivia.iter().map(Trivia::text).collect()
+   }
+
+   #[test]
+   fn splice_inside_keyword() {
+      let tokens = lex("re\\\nturn 0;").expect("lexes");
+      assert!(matches!(tokens[0].kind, TokenKind::Return));
+      assert_eq!((tokens[0].span.line, tokens[0].span.end_line), (1, 2));
+      assert!(matches!(tokens[1].kind, TokenKind::Constant(_)));
+      assert_eq!((tokens[1].span.line, tokens[1].span.column), (2, Some(6)));
+   }
+
+   #[test]
+   fn splice_before_end_of_file() {
+      let tokens = lex("return 0;\\\n").expect("lexes");
+      assert_eq!(tokens.len(), 3);
+      assert!(matches!(tokens[2].kind, TokenKind::Semicolon));
+      // A backslash with no newline after it is not a splice.
+      let error = lex("return 0;\\").expect_err("stray backslash");
+      assert_eq!(error.diagnostic().code, ErrorCode::InvalidToken);
+   }
+
+   #[test]
+   fn block_comment_spanning_a_splice() {
+      let tokens = lex("/\\\n* a *\\\n/ return").expect("lexes");
+      assert_eq!(tokens.len(), 1);
+      assert_eq!(trivia(&tokens[0].leading_trivia), ["/* a */"]);
+      // A splice inside `//` continues the comment onto the next line.
+      let tokens = lex("return; // a \\\n b\n0").expect("lexes");
+      assert_eq!(tokens.len(), 3);
+      assert_eq!(trivia(&tokens[1].trailing_trivia), ["// a  b"]);
+   }
+
+   #[test]
+   fn comments_lead_or_trail_tokens() {
+      let tokens = lex("/* lead */ int /* trail */ x; // end\n// next\nreturn").expect("lexes");
+      assert_eq!(trivia(&tokens[0].leading_trivia), ["/* lead */"]);
+      assert_eq!(trivia(&tokens[0].trailing_trivia), ["/* trail */"]);
+      assert_eq!(trivia(&tokens[2].trailing_trivia), ["// end"]);
+      assert_eq!(trivia(&tokens[3].leading_trivia), ["// next"]);
+      let tokens = lex("return;\n/* last */").expect("lexes");
+      assert_eq!(trivia(&tokens[1].trailing_trivia), ["/* last */"]);
+   }
+
+   #[test]
+   fn unterminated_comment() {
+      let error = lex("int x;\n  /* never closed *").expect_err("unterminated");
+      let diagnostic = error.diagnostic();
+      assert_eq!(diagnostic.code, ErrorCode::UnterminatedComment);
+      let span = diagnostic.span.as_ref().expect("located");
+      assert_eq!((span.line, span.column), (2, Some(3)));
+      // `*/` split by a splice still closes the comment, but `* /` does not.
+      assert!(lex("/* a *\\\n/").is_ok());
+      assert!(lex("/* a * /").is_err());
+   }
+
+   #[test]
+   fn line_markers_relocate_tokens() {
+      let source = "# 1 \"a.c\"\n# 1 \"<built-in>\"\n# 1 \"a.c\"\nint\n# 1 \"inc.h\" 1\nx\n\n;\n# 3 \"a.c\" 2\n#line 20\nreturn";
+      let tokens = lex(source).expect("lexes");
+      let located: Vec<(&str, usize)> = tokens.iter().map(|t| (&*t.span.file, t.span.line)).collect();
+      assert_eq!(located, [("a.c", 1), ("inc.h", 1), ("inc.h", 3), ("a.c", 20)]);
+      let error = lex("# pragma once\n").expect_err("unsupported directive");
+      assert_eq!(error.diagnostic().code, ErrorCode::UnsupportedDirective);
+   }
+
+   #[test]
+   fn encoding_prefixes_follow_the_standard() {
+      for prefix in ["u", "U", "u8"] {
+         let source = format!("{}\"x\"", prefix);
+         let tokens = lex_as(&source, Standard::C11).expect("lexes");
+         assert!(matches!(tokens.as_slice(), [Token { kind: TokenKind::StringLiteral(..), .. }]), "{}", source);
+         // Before C11 the prefix is an ordinary identifier.
+         let tokens = lex_as(&source, Standard::C99).expect("lexes");
+         assert!(matches!(&tokens[0].kind, TokenKind::Identifier(name) if name == prefix), "{}", source);
+         assert!(matches!(tokens[1].kind, TokenKind::StringLiteral(Encoding::Plain, _)), "{}", source);
       }
-      Ok(tokens)
+      let tokens = lex_as("L'a' L\"x\"", Standard::C89).expect("lexes");
+      assert!(matches!(tokens[0].kind, TokenKind::Constant(Constant::Integer(97, IntType::Int))));
+      assert!(matches!(tokens[1].kind, TokenKind::StringLiteral(Encoding::Wide, _)));
+      let tokens = lex_as("u'a' U'a'", Standard::C11).expect("lexes");
+      assert!(matches!(tokens[0].kind, TokenKind::Constant(Constant::Integer(97, IntType::UShort))));
+      assert!(matches!(tokens[1].kind, TokenKind::Constant(Constant::Integer(97, IntType::UInt))));
+   }
+
+   #[test]
+   fn keywords_and_comments_follow_the_standard() {
+      let code = |source: &str, std| lex_as(source, std).map(|_| ()).map_err(|e| e.diagnostic().code);
+      for keyword in ["_Static_assert", "_Alignas", "_Alignof", "_Generic"] {
+         assert_eq!(code(keyword, Standard::C99), Err(ErrorCode::NotInStandard), "{}", keyword);
+         assert_eq!(code(keyword, Standard::C11), Ok(()), "{}", keyword);
+      }
+      assert!(matches!(lex_as("_Bool", Standard::C99).expect("lexes")[0].kind, TokenKind::Bool));
+      let error = lex_as("int x;\nint y; // note", Standard::C89).expect_err("C99 comment");
+      assert_eq!(error.diagnostic().code, ErrorCode::NotInStandard);
+      assert_eq!(error.diagnostic().span.as_ref().map(|s| (s.line, s.column)), Some((2, Some(8))));
+      assert_eq!(code("int x; /* note */", Standard::C89), Ok(()));
+      assert_eq!(code("int x; // note", Standard::C99), Ok(()));
+   }
+
+   #[test]
+   fn utf8_character_constants_are_unsupported() {
+      let error = lex_as("u8'a'", Standard::C23).expect_err("unsupported");
+      assert_eq!(error.diagnostic().code, ErrorCode::Unsupported);
+      assert_eq!(error.diagnostic().span.as_ref().and_then(|s| s.column), Some(1));
+      // Before C23 it is the identifier `u8` followed by a character constant.
+      let tokens = lex_as("u8'a'", Standard::C17).expect("lexes");
+      assert!(matches!(&tokens[0].kind, TokenKind::Identifier(name) if name == "u8"));
+      assert!(matches!(tokens[1].kind, TokenKind::Constant(_)));
    }
-}
\ No newline at end of file
+}
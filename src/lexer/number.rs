@@ -0,0 +1,325 @@
+use crate::cli::Standard;
+use super::token::{Constant, FloatType, IntType};
+
+/// Converts a preprocessing number (as collected by the lexer) into a typed constant.
+pub fn parse(text: &str, std: Standard) -> Result<Constant, String> {
+   let is_hex = text.starts_with("0x") || text.starts_with("0X");
+   let is_binary = text.starts_with("0b") || text.starts_with("0B");
+   if is_binary && std < Standard::C23 {
+      return Err(format!("Binary constant \"{}\" is a C23 feature", text));
+   }
+   let text = &remove_digit_separators(text, is_hex)?;
+   let is_floating = if is_hex {
+      text.contains(['.', 'p', 'P'])
+   } else {
+      !is_binary && text.contains(['.', 'e', 'E'])
+   };
+
+   if is_floating {
+      parse_floating(text, is_hex)
+   } else {
+      parse_integer(text)
+   }
+}
+
+/// Removes C23 digit separators, each of which must sit between two digits. The lexer
+/// only collects them into a number in C23 mode.
+fn remove_digit_separators(text: &str, is_hex: bool) -> Result<String, String> {
+   let is_digit = |c: Option<char>| match c {
+      Some(c) if is_hex => c.is_ascii_hexdigit(),
+      Some(c) => c.is_ascii_digit(),
+      None => false,
+   };
+   let chars: Vec<char> = text.chars().collect();
+   for (i, c) in chars.iter().enumerate() {
+      if *c == '\'' && !(i > 0 && is_digit(chars.get(i - 1).copied()) && is_digit(chars.get(i + 1).copied())) {
+         return Err(format!("Digit separator in \"{}\" must appear between digits", text));
+      }
+   }
+   Ok(text.replace('\'', ""))
+}
+
+fn parse_integer(text: &str) -> Result<Constant, String> {
+   let (radix, base_name, prefix_len) = match text.get(..2) {
+      Some("0x") | Some("0X") => (16, "hexadecimal", 2),
+      Some("0b") | Some("0B") => (2, "binary", 2),
+      _ if text.starts_with('0') => (8, "octal", 1),
+      _ => (10, "decimal", 0),
+   };
+
+   let body = &text[prefix_len..];
+   let digits_len = body.find(|c: char| !c.is_ascii_hexdigit() || (radix != 16 && !c.is_ascii_digit())).unwrap_or(body.len());
+   let (digits, suffix) = body.split_at(digits_len);
+   if digits.is_empty() && radix != 8 {
+      return Err(format!("Invalid {} constant \"{}\": no digits", base_name, text));
+   }
+
+   let mut value: u64 = 0;
+   for d in digits.chars() {
+      let digit = d.to_digit(16).unwrap();
+      if digit >= radix {
+         return Err(format!("Invalid digit '{}' in {} constant \"{}\"", d, base_name, text));
+      }
+      value = value.checked_mul(radix as u64)
+         .and_then(|v| v.checked_add(digit as u64))
+         .ok_or_else(|| format!("Integer constant \"{}\" is too large for its type", text))?;
+   }
+
+   let (is_unsigned, length) = if let Some(rest) = suffix.strip_prefix(['u', 'U']) {
+      (true, rest)
+   } else if let Some(rest) = suffix.strip_suffix(['u', 'U']) {
+      (true, rest)
+   } else {
+      (false, suffix)
+   };
+   let long_count = match length {
+      "" => 0,
+      "l" | "L" => 1,
+      "ll" | "LL" => 2,
+      _ => return Err(format!("Invalid suffix \"{}\" on integer constant", suffix)),
+   };
+
+   // C17 6.4.4.1p5: the first type in the list that can represent the value.
+   // Decimal constants without a 'u' suffix never become unsigned.
+   let candidates: &[IntType] = match (is_unsigned, long_count, radix == 10) {
+      (false, 0, true) => &[IntType::Int, IntType::Long, IntType::LongLong],
+      (false, 0, false) => &[IntType::Int, IntType::UInt, IntType::Long, IntType::ULong, IntType::LongLong, IntType::ULongLong],
+      (true, 0, _) => &[IntType::UInt, IntType::ULong, IntType::ULongLong],
+      (false, 1, true) => &[IntType::Long, IntType::LongLong],
+      (false, 1, false) => &[IntType::Long, IntType::ULong, IntType::LongLong, IntType::ULongLong],
+      (true, 1, _) => &[IntType::ULong, IntType::ULongLong],
+      (false, _, true) => &[IntType::LongLong],
+      (false, _, false) => &[IntType::LongLong, IntType::ULongLong],
+      (true, _, _) => &[IntType::ULongLong],
+   };
+
+   match candidates.iter().find(|t| value <= t.max()) {
+      Some(int_type) => Ok(Constant::Integer(value, *int_type)),
+      None => Err(format!("Integer constant \"{}\" is too large for its type", text)),
+   }
+}
+
+fn parse_floating(text: &str, is_hex: bool) -> Result<Constant, String> {
+   let (body, float_type) = match text.chars().last() {
+      Some('f') | Some('F') => (&text[..text.len()-1], FloatType::Float),
+      Some('l') | Some('L') => (&text[..text.len()-1], FloatType::LongDouble),
+      _ => (text, FloatType::Double),
+   };
+
+   let value = if is_hex {
+      parse_hex_floating(text, &body[2..])?
+   } else {
+      parse_decimal_floating(text, body)?
+   };
+
+   let in_range = match float_type {
+      FloatType::Float => (value as f32).is_finite(),
+      FloatType::Double | FloatType::LongDouble => value.is_finite(),
+   };
+   if !in_range {
+      return Err(format!("Floating constant \"{}\" exceeds the range of its type", text));
+   }
+
+   Ok(Constant::Floating(value, float_type))
+}
+
+fn parse_decimal_floating(text: &str, body: &str) -> Result<f64, String> {
+   let (mantissa, exponent) = match body.find(['e', 'E']) {
+      Some(i) => (&body[..i], Some(&body[i+1..])),
+      None => (body, None),
+   };
+
+   check_mantissa(text, mantissa, |c| c.is_ascii_digit())?;
+   if let Some(exponent) = exponent {
+      check_exponent(text, exponent)?;
+   }
+
+   body.parse::<f64>().map_err(|_| format!("Invalid floating constant \"{}\"", text))
+}
+
+fn parse_hex_floating(text: &str, body: &str) -> Result<f64, String> {
+   let Some(p) = body.find(['p', 'P']) else {
+      return Err(format!("Hexadecimal floating constant \"{}\" requires an exponent", text));
+   };
+   let (mantissa, exponent) = (&body[..p], &body[p+1..]);
+
+   check_mantissa(text, mantissa, |c| c.is_ascii_hexdigit())?;
+   check_exponent(text, exponent)?;
+
+   // Accumulate up to 64 bits of mantissa, folding any further digits into the exponent.
+   let mut bits: u64 = 0;
+   let mut scale: i64 = exponent.parse::<i64>().unwrap_or(if exponent.starts_with('-') { i64::MIN / 2 } else { i64::MAX / 2 });
+   let mut after_point = false;
+   for c in mantissa.chars() {
+      if c == '.' {
+         after_point = true;
+         continue;
+      }
+      let digit = c.to_digit(16).unwrap() as u64;
+      if bits >> 60 == 0 {
+         bits = (bits << 4) | digit;
+         if after_point {
+            scale -= 4;
+         }
+      } else if !after_point {
+         scale += 4;
+      }
+   }
+
+   // Split the scaling so subnormal results do not underflow in the intermediate power.
+   let scale = scale.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+   Ok(bits as f64 * 2f64.powi(scale / 2) * 2f64.powi(scale - scale / 2))
+}
+
+fn check_mantissa(text: &str, mantissa: &str, is_digit: impl Fn(char) -> bool) -> Result<(), String> {
+   let points = mantissa.chars().filter(|c| *c == '.').count();
+   let digits = mantissa.chars().filter(|c| is_digit(*c)).count();
+   if points > 1 || digits == 0 || digits + points != mantissa.len() {
+      return Err(format!("Invalid floating constant \"{}\"", text));
+   }
+   Ok(())
+}
+
+fn check_exponent(text: &str, exponent: &str) -> Result<(), String> {
+   let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+   if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+      return Err(format!("Exponent has no digits in floating constant \"{}\"", text));
+   }
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn integer(text: &str) -> (u64, IntType) {
+      match parse(text, Standard::C23) {
+         Ok(Constant::Integer(value, int_type)) => (value, int_type),
+         other => panic!("{}: expected an integer constant, got {:?}", text, other),
+      }
+   }
+
+   fn floating(text: &str) -> (f64, FloatType) {
+      match parse(text, Standard::C23) {
+         Ok(Constant::Floating(value, float_type)) => (value, float_type),
+         other => panic!("{}: expected a floating constant, got {:?}", text, other),
+      }
+   }
+
+   #[test]
+   fn decimal_constants_never_become_unsigned() {
+      assert_eq!(integer("2147483647"), (2147483647, IntType::Int));
+      assert_eq!(integer("2147483648"), (2147483648, IntType::Long));
+      assert_eq!(integer("9223372036854775807"), (i64::MAX as u64, IntType::Long));
+      assert!(parse("9223372036854775808", Standard::C23).is_err());
+      assert_eq!(integer("2147483648l"), (2147483648, IntType::Long));
+      assert_eq!(integer("1ll"), (1, IntType::LongLong));
+   }
+
+   #[test]
+   fn octal_and_hexadecimal_constants_try_unsigned_types() {
+      assert_eq!(integer("0"), (0, IntType::Int));
+      assert_eq!(integer("017"), (15, IntType::Int));
+      assert_eq!(integer("0x7FFFFFFF"), (0x7FFFFFFF, IntType::Int));
+      assert_eq!(integer("0x80000000"), (0x80000000, IntType::UInt));
+      assert_eq!(integer("037777777777"), (0xFFFFFFFF, IntType::UInt));
+      assert_eq!(integer("0x100000000"), (0x100000000, IntType::Long));
+      assert_eq!(integer("0x8000000000000000"), (0x8000000000000000, IntType::ULong));
+      assert_eq!(integer("0x80000000l"), (0x80000000, IntType::Long));
+      assert_eq!(integer("0x8000000000000000LL"), (0x8000000000000000, IntType::ULongLong));
+   }
+
+   #[test]
+   fn unsigned_suffixes() {
+      assert_eq!(integer("4294967295u"), (4294967295, IntType::UInt));
+      assert_eq!(integer("4294967296U"), (4294967296, IntType::ULong));
+      assert_eq!(integer("1ul"), (1, IntType::ULong));
+      assert_eq!(integer("1LU"), (1, IntType::ULong));
+      assert_eq!(integer("1ull"), (1, IntType::ULongLong));
+      assert_eq!(integer("1LLu"), (1, IntType::ULongLong));
+   }
+
+   #[test]
+   fn invalid_suffixes() {
+      for text in ["1lL", "1Ll", "1uu", "1lul", "1lll", "1x", "1f"] {
+         assert!(parse(text, Standard::C23).is_err(), "{} should be rejected", text);
+      }
+   }
+
+   #[test]
+   fn invalid_digits() {
+      for text in ["08", "0b102", "0x", "0b"] {
+         assert!(parse(text, Standard::C23).is_err(), "{} should be rejected", text);
+      }
+   }
+
+   #[test]
+   fn overflow() {
+      assert_eq!(integer("18446744073709551615u"), (u64::MAX, IntType::ULong));
+      assert_eq!(integer("0xFFFFFFFFFFFFFFFF"), (u64::MAX, IntType::ULong));
+      assert!(parse("18446744073709551616u", Standard::C23).is_err());
+      assert!(parse("0x10000000000000000", Standard::C23).is_err());
+   }
+
+   #[test]
+   fn binary_constants_need_c23() {
+      assert_eq!(integer("0b101"), (5, IntType::Int));
+      assert_eq!(integer("0B11111111111111111111111111111111"), (0xFFFFFFFF, IntType::UInt));
+      assert!(parse("0b101", Standard::C17).is_err());
+   }
+
+   #[test]
+   fn digit_separators() {
+      assert_eq!(integer("1'000'000"), (1000000, IntType::Int));
+      assert_eq!(integer("0xFF'FF"), (0xFFFF, IntType::Int));
+      assert_eq!(integer("0b1'0"), (2, IntType::Int));
+      assert_eq!(floating("1'0.2'5"), (10.25, FloatType::Double));
+      for text in ["1''0", "1'", "0x'F", "1'.5", "0b'1"] {
+         assert!(parse(text, Standard::C23).is_err(), "{} should be rejected", text);
+      }
+   }
+
+   #[test]
+   fn decimal_floating_constants() {
+      assert_eq!(floating("1.5"), (1.5, FloatType::Double));
+      assert_eq!(floating(".5"), (0.5, FloatType::Double));
+      assert_eq!(floating("1."), (1.0, FloatType::Double));
+      assert_eq!(floating("1e3"), (1000.0, FloatType::Double));
+      assert_eq!(floating("25E-2"), (0.25, FloatType::Double));
+      assert_eq!(floating("1.5f"), (1.5, FloatType::Float));
+      assert_eq!(floating("1.5L"), (1.5, FloatType::LongDouble));
+      for text in ["1e", "1e+", "1.2.3", "1.5u"] {
+         assert!(parse(text, Standard::C23).is_err(), "{} should be rejected", text);
+      }
+   }
+
+   #[test]
+   fn hexadecimal_floating_constants() {
+      assert_eq!(floating("0x1p4"), (16.0, FloatType::Double));
+      assert_eq!(floating("0x1.8p1"), (3.0, FloatType::Double));
+      assert_eq!(floating("0x.8p0"), (0.5, FloatType::Double));
+      assert_eq!(floating("0XAP-2"), (2.5, FloatType::Double));
+      assert_eq!(floating("0x1p3f"), (8.0, FloatType::Float));
+      assert_eq!(floating("0x1.fffffffffffffp1023"), (f64::MAX, FloatType::Double));
+      assert!(parse("0x1.0", Standard::C23).is_err());
+      assert!(parse("0x1p", Standard::C23).is_err());
+   }
+
+   #[test]
+   fn hexadecimal_floating_scaling() {
+      // Subnormals must not underflow halfway through the scaling.
+      assert_eq!(floating("0x1p-1074").0, f64::from_bits(1));
+      assert_eq!(floating("0x0.0000000000001p-1022").0, f64::from_bits(1));
+      // Digits beyond 64 bits of mantissa only move the exponent.
+      assert_eq!(floating("0x123456789abcdef01p0").0, 0x123456789abcdef01u128 as f64);
+      assert_eq!(floating("0x0.000000000000000000001p84").0, 1.0);
+   }
+
+   #[test]
+   fn floating_constants_out_of_range() {
+      assert!(parse("0x1p1024", Standard::C23).is_err());
+      assert!(parse("1e309", Standard::C23).is_err());
+      assert!(parse("1e39f", Standard::C23).is_err());
+      assert_eq!(floating("3.4e38f").1, FloatType::Float);
+   }
+}
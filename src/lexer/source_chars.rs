@@ -0,0 +1,143 @@
+use std::str::Chars;
+
+/// Where a character sits in the source text: its byte offset and its 1-based
+/// physical line and column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+   pub offset: usize,
+   pub line: usize,
+   pub column: usize,
+}
+
+/// Iterates over the characters of a source file with backslash-newline
+/// line splices removed (translation phase 2), keeping track of the position.
+#[derive(Clone)]
+pub struct SourceChars<'a> {
+   chars: Chars<'a>,
+   position: Position,
+}
+
+impl<'a> SourceChars<'a> {
+   pub fn new(source: &'a str) -> Self {
+      Self {
+         chars: source.chars(),
+         position: Position { offset: 0, line: 1, column: 1 },
+      }
+   }
+
+   /// The position of the next character to be read, or of a splice before it.
+   pub fn position(&self) -> Position {
+      self.position
+   }
+
+   pub fn peek(&self) -> Option<char> {
+      self.clone().next()
+   }
+
+   /// Consumes the next character if `f` accepts it.
+   pub fn next_if(&mut self, f: impl FnOnce(&char) -> bool) -> Option<char> {
+      let mut lookahead = self.clone();
+      match lookahead.next() {
+         Some(c) if f(&c) => {
+            *self = lookahead;
+            Some(c)
+         },
+         _ => None,
+      }
+   }
+
+   pub fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+      self.next_if(|c| c == expected)
+   }
+
+   /// Reads one physical character, advancing the position past it.
+   fn next_raw(&mut self) -> Option<char> {
+      let c = self.chars.next()?;
+      self.position.offset += c.len_utf8();
+      if c == '\n' {
+         self.position.line += 1;
+         self.position.column = 1;
+      } else {
+         self.position.column += 1;
+      }
+      Some(c)
+   }
+}
+
+impl Iterator for SourceChars<'_> {
+   type Item = char;
+
+   fn next(&mut self) -> Option<char> {
+      loop {
+         let c = self.next_raw()?;
+         if c != '\\' {
+            return Some(c);
+         }
+
+         let mut lookahead = self.chars.clone();
+         match lookahead.next() {
+            Some('\n') => {
+               self.next_raw();
+            },
+            Some('\r') if lookahead.next() == Some('\n') => {
+               self.next_raw();
+               self.next_raw();
+            },
+            _ => return Some(c)
+         }
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn spliced(source: &str) -> String {
+      SourceChars::new(source).collect()
+   }
+
+   #[test]
+   fn splices_are_removed() {
+      assert_eq!(spliced("re\\\nturn"), "return");
+      assert_eq!(spliced("re\\\r\nturn"), "return");
+      assert_eq!(spliced("a\\\n\\\nb"), "ab");
+      // A backslash not followed by a newline is an ordinary character.
+      assert_eq!(spliced("a\\b"), "a\\b");
+      assert_eq!(spliced("a\\\rb"), "a\\\rb");
+   }
+
+   #[test]
+   fn splice_before_end_of_file() {
+      assert_eq!(spliced("x;\\\n"), "x;");
+      assert_eq!(spliced("x;\\"), "x;\\");
+      let mut chars = SourceChars::new("x\\\n");
+      assert_eq!(chars.next(), Some('x'));
+      assert_eq!(chars.next(), None);
+      assert_eq!(chars.position(), Position { offset: 3, line: 2, column: 1 });
+   }
+
+   #[test]
+   fn positions_follow_physical_lines() {
+      let mut chars = SourceChars::new("ab\\\ncd\ne");
+      assert_eq!(chars.position(), Position { offset: 0, line: 1, column: 1 });
+      chars.next();
+      chars.next();
+      // The splice is skipped along with the character after it.
+      assert_eq!(chars.position(), Position { offset: 2, line: 1, column: 3 });
+      assert_eq!(chars.next(), Some('c'));
+      assert_eq!(chars.position(), Position { offset: 5, line: 2, column: 2 });
+      assert_eq!(chars.nth(1), Some('\n'));
+      assert_eq!(chars.position(), Position { offset: 7, line: 3, column: 1 });
+   }
+
+   #[test]
+   fn lookahead_sees_through_splices() {
+      let mut chars = SourceChars::new("+\\\n+");
+      assert_eq!(chars.next(), Some('+'));
+      assert_eq!(chars.peek(), Some('+'));
+      assert_eq!(chars.next_if_eq(&'-'), None);
+      assert_eq!(chars.next_if_eq(&'+'), Some('+'));
+      assert_eq!(chars.next(), None);
+   }
+}
@@ -1,20 +1,197 @@
+use crate::span::Span;
+
 #[derive(Debug)]
-pub enum Token {
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    /// Comments between the previous token's line and this token.
+    pub leading_trivia: Vec<Trivia>,
+    /// Comments that follow this token on the same line.
+    pub trailing_trivia: Vec<Trivia>,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, span: Span, leading_trivia: Vec<Trivia>) -> Self {
+        Token {
+            kind,
+            span,
+            leading_trivia,
+            trailing_trivia: Vec::new(),
+        }
+    }
+
+    pub fn pretty_print(&self) {
+        for trivia in &self.leading_trivia {
+            println!("{}", trivia.text());
+        }
+        print!("{:?}", self.kind);
+        for trivia in &self.trailing_trivia {
+            print!(" {}", trivia.text());
+        }
+        println!();
+    }
+}
+
+#[derive(Debug)]
+pub enum Trivia {
+    LineComment(String),
+    BlockComment(String),
+}
+
+impl Trivia {
+    /// The comment as it appeared in the source, delimiters included.
+    pub fn text(&self) -> String {
+        match self {
+            Trivia::LineComment(t) => format!("//{}", t),
+            Trivia::BlockComment(t) => format!("/*{}*/", t),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TokenKind {
     OpenParen,
     CloseParen,
     OpenBrace,
     CloseBrace,
+    OpenBracket,
+    CloseBracket,
     Semicolon,
+    Comma,
+    Assign,
+    Ellipsis,
     Int,
     Void,
+    Char,
+    Short,
+    Long,
+    Signed,
+    Unsigned,
+    Float,
+    Double,
+    Bool,
+    Typedef,
+    Extern,
+    Static,
+    Auto,
+    Register,
+    Const,
+    Volatile,
+    Restrict,
+    Inline,
+    Noreturn,
     Return,
-    Integer(u64),
+    Default,
+    Sizeof,
+    StaticAssert,
+    Alignas,
+    Alignof,
+    Generic,
+    Nullptr,
+    Typeof,
+    TypeofUnqual,
+    Constexpr,
+    Constant(Constant),
+    StringLiteral(Encoding, Vec<StringChar>),
     Identifier(String),
     Negate,
     BitFlip,
     Decrement,
+    Increment,
     Plus,
+    PlusAssign,
     Asterisk,
     ForwardSlash,
     Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
+    LogicalNot,
+    LogicalAnd,
+    LogicalOr,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
+    Question,
+    Colon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constant {
+    /// The value as a 64-bit pattern; only character constants can be negative.
+    Integer(u64, IntType),
+    Floating(f64, FloatType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntType {
+    /// bool, the type of the C23 constants true and false.
+    Bool,
+    Int,
+    UInt,
+    /// char16_t, the type of u'' character constants.
+    UShort,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
+}
+
+impl IntType {
+    /// Largest value representable in the type on x86-64 (LP64).
+    pub fn max(&self) -> u64 {
+        match self {
+            IntType::Bool => 1,
+            IntType::Int => i32::MAX as u64,
+            IntType::UInt => u32::MAX as u64,
+            IntType::UShort => u16::MAX as u64,
+            IntType::Long | IntType::LongLong => i64::MAX as u64,
+            IntType::ULong | IntType::ULongLong => u64::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatType {
+    Float,
+    Double,
+    LongDouble,
+}
+
+/// The encoding prefix of a character constant or string literal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Plain,
+    /// u8. Only string literals are supported; u8 character constants (C23) are not.
+    Utf8,
+    /// L, with wchar_t elements
+    Wide,
+    /// u, with char16_t elements
+    Utf16,
+    /// U, with char32_t elements
+    Utf32,
+}
+
+impl Encoding {
+    /// Largest code unit of the element type on x86-64 Linux.
+    pub fn max_unit(&self) -> u64 {
+        match self {
+            Encoding::Plain | Encoding::Utf8 => u8::MAX as u64,
+            Encoding::Utf16 => u16::MAX as u64,
+            Encoding::Wide | Encoding::Utf32 => u32::MAX as u64,
+        }
+    }
+}
+
+/// One element of a character constant or string literal before encoding: a source
+/// character or universal character name, or a code unit given by an octal or hex escape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StringChar {
+    CodePoint(char),
+    Unit(u32),
 }
@@ -1,33 +1,56 @@
 mod assembler;
 mod assembly;
 mod cli;
+mod error;
 mod lexer;
 mod parser;
 mod preprocessor;
+mod semantic;
+mod span;
 mod tacky;
 
 use assembler::Assembler;
 use assembly::AssemblyGen;
 use assembly::at;
 use cli::Cli;
+use error::CompileError;
 use lexer::Lexer;
 use parser::Parser;
 use preprocessor::Preprocessor;
-use std::error::Error;
-use std::fs::File;
+use semantic::Validator;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
     let args = Cli::do_parse();
+    match compile(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            // Diagnostics go to stderr, but whether to color them follows stdout, so that
+            // redirecting the normal output also turns color off.
+            let color = io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+            eprint!("{}", error.render(color));
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
 
+fn compile(args: &Cli) -> Result<(), CompileError> {
     if args.command.run_lexer {
-        let pp_contents = Preprocessor::new(&args.file).process()?;
-        let tokens = Lexer::new(pp_contents).lex()?;
+        let source = if args.no_preprocess {
+            fs::read_to_string(&args.file)?
+        } else {
+            Preprocessor::new(&args.file, args.std).process()?
+        };
+        let tokens = Lexer::new(source, &args.file, args.std).lex()?;
         for token in &tokens {
-            println!("{:?}", token);
+            token.pretty_print();
         }
 
         if args.command.run_parser {
-            let program = Parser::new(tokens).parse()?;
+            let program = Parser::new(tokens, args.std).parse()?;
+            Validator::new(&program, args.std).validate()?;
             program.pretty_print();
 
             if args.command.run_tacky {
@@ -35,8 +58,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 tacky_program.pretty_print();
 
                 if args.command.run_codegen {
-                    let at_program: at::Program;
-                    at_program = AssemblyGen::new(tacky_program).parse()?;
+                    let at_program: at::Program = AssemblyGen::new(tacky_program).parse()?;
                     at_program.pretty_print();
 
                     if args.command.run_assembler {
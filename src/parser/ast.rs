@@ -1,105 +1,526 @@
-use crate::lexer::token::Token;
+use crate::cli::Standard;
+use crate::error::{CompileError, ErrorCode};
+use crate::lexer::token::{Constant, IntType, StringChar, Token, TokenKind};
+use super::declarator::Declarator;
+use super::types::{DeclSpecifiers, Param, Params, StorageClass, Type};
+use super::scope::ScopeStack;
+use crate::span::Span;
+use super::{parse_string_literals, peek_kind, skip_attributes, ParseContext, TokenStream};
 
-macro_rules! expect_token {
-   ($actual_token:expr, $expected_token:path, $msg:literal) => {
-      if let Some(token) = $actual_token {
-         match token {
-            $expected_token => (),
-            _ => {
-               return Err(String::from($msg));
+pub enum Program {
+   Program(Vec<ExternalDecl>),
+}
+
+impl Program {
+   pub fn pretty_print(&self) {
+      println!("Program(");
+      match self {
+         Self::Program(decls) => {
+            for decl in decls {
+               decl.pretty_print(1);
             }
          }
-      } else {
-         return Err(String::from($msg));
       }
-   };
+      println!(")")
+   }
+
+   /// Parses a translation unit. Errors are located at the last token read before
+   /// the parser gave up.
+   pub fn parse(tokens: &[Token], std: Standard) -> Result<Self, CompileError> {
+      let mut token_stream = TokenStream::new(tokens);
+      let mut ctx = ParseContext { scope: ScopeStack::new(), std };
+      let mut decls: Vec<ExternalDecl> = Vec::new();
+      while token_stream.peek().is_some() {
+         let decl = Self::parse_external_decl(&mut token_stream, &mut ctx)
+            .map_err(|e| e.at(token_stream.previous_span()))?;
+         decls.extend(decl);
+      }
+      Ok(Program::Program(decls))
+   }
+
+   fn parse_external_decl(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Option<ExternalDecl>, CompileError> {
+      // An attribute declaration (`[[...]];`) declares nothing.
+      if skip_attributes(token_stream, ctx)? && let Some(TokenKind::Semicolon) = peek_kind(token_stream) {
+         token_stream.next();
+         return Ok(None);
+      }
+      ExternalDecl::parse(token_stream, ctx).map(Some)
+   }
 }
 
-macro_rules! expect_assign_token {
-   ($actual_token:expr, $expected_token:path, $var_name:ident, $type:ty, $msg:literal) => {
-      let $var_name: $type;
-      if let Some(token) = $actual_token {
-         match token {
-            $expected_token(value) => $var_name = value.clone(),
-            _ => {
-               return Err(String::from($msg));
-            }
+pub enum ExternalDecl {
+   Function(Function),
+   Declaration(Declaration),
+   StaticAssert(StaticAssert),
+}
+
+impl ExternalDecl {
+   pub fn pretty_print(&self, indent_level: usize) {
+      match self {
+         Self::Function(f) => f.pretty_print(indent_level),
+         Self::Declaration(d) => d.pretty_print(indent_level),
+         Self::StaticAssert(s) => s.pretty_print(indent_level),
+      }
+   }
+
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      if let Some(TokenKind::StaticAssert) = peek_kind(token_stream) {
+         return Ok(ExternalDecl::StaticAssert(StaticAssert::parse(token_stream, ctx)?));
+      }
+      let declaration_start = token_stream.next_span();
+      let specifiers = DeclSpecifiers::parse(token_stream, ctx)?;
+      if matches!(specifiers.storage_class, Some(StorageClass::Auto) | Some(StorageClass::Register)) {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid storage class for a file-scope declaration"));
+      }
+      if let Some(TokenKind::Semicolon) = peek_kind(token_stream) {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Declaration does not declare anything"));
+      }
+
+      let start = token_stream.next_span();
+      let declarator = Declarator::parse(token_stream, ctx, false)?;
+      let declares_function = declarator.declares_function();
+      let (name, decl_type) = declarator.apply(specifiers.base_type.clone())?;
+      let name = name.unwrap_or_default();
+      let span = token_stream.span_from(start);
+      // An identifier list can only begin a definition; otherwise a body must follow.
+      let is_definition = match decl_type.unqualified() {
+         Type::Function(_, Params::OldStyle(_)) => true,
+         Type::Function(..) => matches!(peek_kind(token_stream), Some(TokenKind::OpenBrace)),
+         _ => false,
+      };
+      if is_definition {
+         if !declares_function {
+            let message = format!("Function definition of \"{}\" must declare its parameters; the function type cannot come from a typedef", name);
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, message).at(&span));
          }
+         Ok(ExternalDecl::Function(Function::parse(token_stream, ctx, specifiers, name, decl_type, span)?))
       } else {
-         return Err(String::from($msg));
+         Ok(ExternalDecl::Declaration(Declaration::parse_init_declarators(token_stream, ctx, declaration_start, specifiers, name, decl_type, span)?))
       }
-   };
+   }
 }
 
-pub enum Program {
-   Program(FuncDef),
+pub struct Function {
+   pub name: String,
+   pub specifiers: DeclSpecifiers,
+   pub func_type: Type,
+   pub body: Vec<BlockItem>,
+   /// The span of the declarator.
+   pub span: Span,
 }
 
-impl Program {
-   pub fn pretty_print(&self) {
-      println!("Program(");
-      match self {
-         Self::Program(f) => f.pretty_print(1),
+impl Function {
+   pub fn pretty_print(&self, indent_level: usize) {
+      println!("{:indent$}Function(", "", indent=indent_level*3);
+      println!("{:indent$}name=\"{name}\"", "", indent=indent_level*6, name=self.name);
+      println!("{:indent$}type={t}", "", indent=indent_level*6, t=self.func_type);
+      self.specifiers.pretty_print(indent_level*2);
+      println!("{:indent$}body=(", "", indent=indent_level*6);
+      for item in &self.body {
+         item.pretty_print(indent_level+2);
       }
-      println!(")")
+      println!("{:indent$})", "", indent=indent_level*6);
+      println!("{:indent$})", "", indent=indent_level*3);
    }
 
-   pub fn parse(tokens: &Vec<Token>) -> Result<Self, String> {
-      let mut token_stream = tokens.iter();
-      let parse_result = FuncDef::parse(&mut token_stream)?;
+   /// Parses the body of a function definition whose declarator has already been read.
+   fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext, specifiers: DeclSpecifiers, name: String, func_type: Type, span: Span) -> Result<Self, CompileError> {
+      if !matches!(specifiers.storage_class, None | Some(StorageClass::Extern) | Some(StorageClass::Static)) {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid storage class for function definition"));
+      }
+      let Type::Function(ret, params) = func_type else {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("\"{}\" is not declared as a function", name)));
+      };
+      if !specifiers.alignment.is_empty() {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("_Alignas is not allowed on function \"{}\"", name)));
+      }
+      if ret.has_identifier_list() {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Parameter names without types in the return type of \"{}\"", name)));
+      }
+
+      // The parameters share a scope with the outermost block of the body.
+      ctx.scope.declare(&name, None);
+      ctx.scope.enter();
+      let params_and_body = Self::parse_params_and_body(token_stream, ctx, params);
+      ctx.scope.exit();
+      let (params, body) = params_and_body?;
+
+      Ok(Function { name, specifiers, func_type: Type::Function(ret, params), body, span })
+   }
+
+   fn parse_params_and_body(token_stream: &mut TokenStream, ctx: &mut ParseContext, params: Params) -> Result<(Params, Vec<BlockItem>), CompileError> {
+      let params = match params {
+         Params::Prototype(params, is_variadic) => {
+            for param in &params {
+               match &param.name {
+                  Some(param_name) => ctx.scope.declare(param_name, None),
+                  None => {
+                     return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Parameter name omitted in function definition"));
+                  }
+               }
+            }
+            Params::Prototype(params, is_variadic)
+         },
+         Params::OldStyle(params) => Params::OldStyle(Self::parse_declaration_list(token_stream, ctx, params)?),
+         Params::Unspecified => Params::Unspecified,
+      };
+      let body = BlockItem::parse_block(token_stream, ctx)?;
+      Ok((params, body))
+   }
+
+   /// Reads the declaration list between a K&R declarator and the body, giving each
+   /// parameter its declared type.
+   fn parse_declaration_list(token_stream: &mut TokenStream, ctx: &mut ParseContext, mut params: Vec<Param>) -> Result<Vec<Param>, CompileError> {
+      for param in &params {
+         if let Some(param_name) = &param.name {
+            ctx.scope.declare(param_name, None);
+         }
+      }
+
+      let mut declared = vec![false; params.len()];
+      while !matches!(peek_kind(token_stream), Some(TokenKind::OpenBrace) | None) {
+         let declaration = Declaration::parse(token_stream, ctx)?;
+         if !matches!(declaration.specifiers.storage_class, None | Some(StorageClass::Register)) {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid storage class for parameter"));
+         }
+         if !declaration.specifiers.alignment.is_empty() {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "_Alignas is not allowed on parameters"));
+         }
+         for declarator in declaration.declarators {
+            let Some(i) = params.iter().position(|p| p.name.as_deref() == Some(declarator.name.as_str())) else {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Declaration for parameter \"{}\" but no such parameter", declarator.name)));
+            };
+            if declared[i] {
+               return Err(CompileError::parser(ErrorCode::Redefinition, format!("Parameter \"{}\" declared more than once", declarator.name)));
+            }
+            if declarator.init.is_some() {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Parameter \"{}\" cannot have an initializer", declarator.name)));
+            }
+            if declarator.decl_type.is_void() {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Parameter \"{}\" has void type", declarator.name)));
+            }
+            params[i].param_type = Declarator::adjust_param_type(declarator.decl_type.clone());
+            params[i].declared_type = declarator.decl_type;
+            declared[i] = true;
+         }
+      }
 
-      if let Some(_) = token_stream.next() {
-         return Err(String::from("Unexpected junk found"));
+      // Only C89 lets an undeclared parameter default to int.
+      if ctx.std != Standard::C89 && let Some(i) = declared.iter().position(|d| !d) {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Type of parameter \"{}\" is not declared", params[i].name.as_deref().unwrap_or_default())));
       }
-      Ok(Program::Program(parse_result))
+      Ok(params)
    }
 }
 
-pub struct Function {
+pub struct Declaration {
+   pub specifiers: DeclSpecifiers,
+   pub declarators: Vec<InitDeclarator>,
+   /// The whole declaration, from the specifiers to the semicolon.
+   pub span: Span,
+}
+
+pub struct InitDeclarator {
    pub name: String,
-   pub stmt: Stmt,
+   pub decl_type: Type,
+   pub init: Option<Initializer>,
+   /// The span of the declarator, not including the initializer.
+   pub span: Span,
 }
 
-pub enum FuncDef {
-   Function(Function),
+impl Declaration {
+   pub fn pretty_print(&self, indent_level: usize) {
+      println!("{:indent$}Declaration(", "", indent=indent_level*3);
+      self.specifiers.pretty_print(indent_level+1);
+      for declarator in &self.declarators {
+         println!("{:indent$}Declarator(", "", indent=(indent_level+1)*3);
+         println!("{:indent$}name=\"{name}\"", "", indent=(indent_level+2)*3, name=declarator.name);
+         println!("{:indent$}type={t}", "", indent=(indent_level+2)*3, t=declarator.decl_type);
+         if let Some(init) = &declarator.init {
+            println!("{:indent$}init=(", "", indent=(indent_level+2)*3);
+            init.pretty_print(indent_level+3);
+            println!("{:indent$})", "", indent=(indent_level+2)*3);
+         }
+         println!("{:indent$})", "", indent=(indent_level+1)*3);
+      }
+      println!("{:indent$})", "", indent=indent_level*3);
+   }
+
+   /// Parses a block-scope declaration.
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      let declaration_start = token_stream.next_span();
+      let specifiers = DeclSpecifiers::parse(token_stream, ctx)?;
+      if let Some(TokenKind::Semicolon) = peek_kind(token_stream) {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Declaration does not declare anything"));
+      }
+
+      let start = token_stream.next_span();
+      let (name, decl_type) = Declarator::parse(token_stream, ctx, false)?.apply(specifiers.base_type.clone())?;
+      let span = token_stream.span_from(start);
+      let declaration = Self::parse_init_declarators(token_stream, ctx, declaration_start, specifiers, name.unwrap_or_default(), decl_type, span)?;
+      let has_local_function = declaration.declarators.iter().any(|d| d.decl_type.is_function());
+      if has_local_function && !matches!(declaration.specifiers.storage_class, None | Some(StorageClass::Extern) | Some(StorageClass::Typedef)) {
+         let error = CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid storage class for a block-scope function declaration");
+         return Err(error.at(&declaration.span));
+      }
+      Ok(declaration)
+   }
+
+   /// Parses the rest of an init-declarator list, given its first declarator, up to and
+   /// including the closing semicolon. `start` is where the declaration specifiers began.
+   fn parse_init_declarators(token_stream: &mut TokenStream, ctx: &mut ParseContext, start: &Span, specifiers: DeclSpecifiers, name: String, decl_type: Type, span: Span) -> Result<Self, CompileError> {
+      let is_typedef = specifiers.storage_class == Some(StorageClass::Typedef);
+      let mut declarators: Vec<InitDeclarator> = Vec::new();
+      let (mut name, mut decl_type, mut span) = (name, decl_type, span);
+      loop {
+         if decl_type.has_identifier_list() {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Parameter names without types in the declaration of \"{}\"", name)));
+         }
+         if (specifiers.is_inline || specifiers.is_noreturn) && !decl_type.is_function() {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Function specifier used in the declaration of non-function \"{}\"", name)));
+         }
+         if !specifiers.alignment.is_empty() {
+            if is_typedef {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("_Alignas is not allowed in typedef \"{}\"", name)));
+            }
+            if decl_type.is_function() {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("_Alignas is not allowed on function \"{}\"", name)));
+            }
+            if specifiers.storage_class == Some(StorageClass::Register) {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("_Alignas is not allowed on register object \"{}\"", name)));
+            }
+         }
+         if specifiers.is_constexpr && decl_type.is_function() {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Function \"{}\" cannot be constexpr", name)));
+         }
+         // A declarator's scope begins as soon as the declarator is complete, before its initializer.
+         ctx.scope.declare(&name, if is_typedef { Some(decl_type.clone()) } else { None });
+
+         let init = match peek_kind(token_stream) {
+            Some(TokenKind::Assign) => {
+               token_stream.next();
+               if is_typedef {
+                  return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Typedef \"{}\" cannot be initialized", name)));
+               }
+               if decl_type.is_function() {
+                  return Err(CompileError::parser(ErrorCode::InvalidDeclaration, format!("Function \"{}\" cannot have an initializer", name)));
+               }
+               Some(Initializer::parse(token_stream, ctx)?)
+            },
+            _ => None,
+         };
+         // From here on the name of a constexpr object stands for its value.
+         if specifiers.is_constexpr {
+            let Some(value) = init.as_ref().and_then(Initializer::scalar_value) else {
+               return Err(CompileError::parser(ErrorCode::InvalidInitializer, format!("constexpr object \"{}\" requires a single initializer expression", name)));
+            };
+            let value_span = value.span.clone();
+            ctx.scope.declare_constexpr(&name, Expr::new(ExprKind::Cast(Box::new(decl_type.clone()), Box::new(value)), value_span));
+         }
+         declarators.push(InitDeclarator { name, decl_type, init, span });
+
+         match token_stream.next().map(|t| &t.kind) {
+            Some(TokenKind::Comma) => {
+               let start = token_stream.next_span();
+               let (next_name, next_type) = Declarator::parse(token_stream, ctx, false)?.apply(specifiers.base_type.clone())?;
+               name = next_name.unwrap_or_default();
+               decl_type = next_type;
+               span = token_stream.span_from(start);
+            },
+            Some(TokenKind::Semicolon) => break,
+            _ => {
+               return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected a semicolon after declaration"));
+            }
+         }
+      }
+
+      Ok(Declaration { specifiers, declarators, span: token_stream.span_from(start) })
+   }
+}
+
+#[derive(Clone)]
+pub enum Initializer {
+   Single(Expr),
+   List(Vec<Initializer>, Span),
 }
 
-impl FuncDef {
+impl Initializer {
    pub fn pretty_print(&self, indent_level: usize) {
       match self {
-         Self::Function(f) => {
-            println!("{:indent$}Function(", "", indent=indent_level*3);
-            println!("{:indent$}name=\"{name}\"", "", indent=indent_level*6, name=f.name);
-            println!("{:indent$}body=(", "", indent=indent_level*6);
-            f.stmt.pretty_print(indent_level+2);
-            println!("{:indent$})", "", indent=indent_level*6);
+         Self::Single(e) => e.pretty_print(indent_level),
+         Self::List(inits, _) => {
+            println!("{:indent$}List(", "", indent=indent_level*3);
+            for init in inits {
+               init.pretty_print(indent_level+1);
+            }
             println!("{:indent$})", "", indent=indent_level*3);
          }
       }
    }
 
-   pub fn parse<'a>(token_stream: &mut impl Iterator<Item=&'a Token>) -> Result<Self, String> {
-      expect_token!(token_stream.next(), Token::Int, "Syntax Error: expected an int");
-      expect_assign_token!(token_stream.next(), Token::Identifier, func_name, String, "Syntax Error");
-      expect_token!(token_stream.next(), Token::OpenParen, "Syntax Error: expected open paren");
-      expect_token!(token_stream.next(), Token::Void, "Syntax Error: expected void");
-      expect_token!(token_stream.next(), Token::CloseParen, "Syntax Error: expected closing paren");
-      expect_token!(token_stream.next(), Token::OpenBrace, "Syntax Error: expected open brace");
-      let statement: Stmt = Stmt::parse(token_stream)?;
-      expect_token!(token_stream.next(), Token::CloseBrace, "Syntax Error: expected closing brace");
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      if !matches!(peek_kind(token_stream), Some(TokenKind::OpenBrace)) {
+         return Ok(Initializer::Single(Expr::parse(token_stream, ctx)?));
+      }
 
-      Ok(FuncDef::Function( Function { name: func_name, stmt: statement } ))
+      let start = token_stream.next_span();
+      token_stream.next();
+      let mut inits: Vec<Initializer> = Vec::new();
+      if let Some(TokenKind::CloseBrace) = peek_kind(token_stream) {
+         if ctx.std < Standard::C23 {
+            return Err(CompileError::parser(ErrorCode::NotInStandard, "Empty initializer braces are a C23 feature"));
+         }
+         token_stream.next();
+         return Ok(Initializer::List(inits, token_stream.span_from(start)));
+      }
+      loop {
+         inits.push(Initializer::parse(token_stream, ctx)?);
+         match token_stream.next().map(|t| &t.kind) {
+            Some(TokenKind::Comma) => {
+               // A trailing comma may precede the closing brace.
+               if let Some(TokenKind::CloseBrace) = peek_kind(token_stream) {
+                  token_stream.next();
+                  break;
+               }
+            },
+            Some(TokenKind::CloseBrace) => break,
+            _ => {
+               return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected closing brace after initializer list"));
+            }
+         }
+      }
+      Ok(Initializer::List(inits, token_stream.span_from(start)))
+   }
+
+   /// The expression a scalar is initialized with: `e`, `{e}`, or zero for `{}`.
+   pub fn scalar_value(&self) -> Option<Expr> {
+      match self {
+         Self::Single(e) => Some(e.clone()),
+         Self::List(inits, span) => match inits.as_slice() {
+            [] => Some(Expr::new(ExprKind::Const(Constant::Integer(0, IntType::Int)), span.clone())),
+            [Self::Single(e)] => Some(e.clone()),
+            _ => None,
+         },
+      }
    }
 }
 
-pub enum Stmt {
+pub struct StaticAssert {
+   pub condition: Expr,
+   /// Optional from C23 on.
+   pub message: Option<String>,
+   pub span: Span,
+}
+
+impl StaticAssert {
+   pub fn pretty_print(&self, indent_level: usize) {
+      println!("{:indent$}StaticAssert(", "", indent=indent_level*3);
+      self.condition.pretty_print(indent_level+1);
+      if let Some(message) = &self.message {
+         println!("{:indent$}message=\"{message}\"", "", indent=(indent_level+1)*3, message=message);
+      }
+      println!("{:indent$})", "", indent=indent_level*3);
+   }
+
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      let start = token_stream.next_span();
+      expect_token!(token_stream.next(), TokenKind::StaticAssert, "Syntax Error: expected _Static_assert");
+      expect_token!(token_stream.next(), TokenKind::OpenParen, "Syntax Error: expected open paren after _Static_assert");
+      let condition = Expr::parse(token_stream, ctx)?;
+      let message = match peek_kind(token_stream) {
+         Some(TokenKind::CloseParen) if ctx.std >= Standard::C23 => None,
+         Some(TokenKind::CloseParen) => {
+            return Err(CompileError::parser(ErrorCode::NotInStandard, "_Static_assert without a message is a C23 feature"));
+         },
+         _ => {
+            expect_token!(token_stream.next(), TokenKind::Comma, "Syntax Error: expected a comma before the _Static_assert message");
+            // Code units from escapes outside ASCII have no character of their own, so they stay escaped.
+            let (_, chars) = parse_string_literals(token_stream)?;
+            Some(chars.iter().map(|c| match c {
+               StringChar::CodePoint(c) => c.to_string(),
+               StringChar::Unit(u) if *u < 0x80 => char::from(*u as u8).to_string(),
+               StringChar::Unit(u) => format!("\\x{:x}", u),
+            }).collect())
+         },
+      };
+      expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren in _Static_assert");
+      expect_token!(token_stream.next(), TokenKind::Semicolon, "Syntax Error: expected a semicolon after _Static_assert");
+      Ok(StaticAssert { condition, message, span: token_stream.span_from(start) })
+   }
+}
+
+pub enum BlockItem {
+   Declaration(Declaration),
+   StaticAssert(StaticAssert),
+   Stmt(Stmt),
+}
+
+impl BlockItem {
+   pub fn pretty_print(&self, indent_level: usize) {
+      match self {
+         Self::Declaration(d) => d.pretty_print(indent_level),
+         Self::StaticAssert(s) => s.pretty_print(indent_level),
+         Self::Stmt(s) => s.pretty_print(indent_level),
+      }
+   }
+
+   pub fn span(&self) -> &Span {
+      match self {
+         Self::Declaration(d) => &d.span,
+         Self::StaticAssert(s) => &s.span,
+         Self::Stmt(s) => &s.span,
+      }
+   }
+
+   /// Parses a brace-enclosed list of block items. The caller manages the scope.
+   pub fn parse_block(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Vec<Self>, CompileError> {
+      expect_token!(token_stream.next(), TokenKind::OpenBrace, "Syntax Error: expected open brace");
+      let mut items: Vec<BlockItem> = Vec::new();
+      loop {
+         if skip_attributes(token_stream, ctx)? && let Some(TokenKind::Semicolon) = peek_kind(token_stream) {
+            token_stream.next();
+            continue;
+         }
+         match peek_kind(token_stream) {
+            Some(TokenKind::CloseBrace) => {
+               token_stream.next();
+               break;
+            },
+            None => {
+               return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected closing brace"));
+            },
+            Some(TokenKind::StaticAssert) => {
+               items.push(BlockItem::StaticAssert(StaticAssert::parse(token_stream, ctx)?));
+            },
+            _ if DeclSpecifiers::starts_declaration(token_stream, ctx) => {
+               items.push(BlockItem::Declaration(Declaration::parse(token_stream, ctx)?));
+            },
+            _ => {
+               items.push(BlockItem::Stmt(Stmt::parse(token_stream, ctx)?));
+            }
+         }
+      }
+      Ok(items)
+   }
+}
+
+pub struct Stmt {
+   pub kind: StmtKind,
+   pub span: Span,
+}
+
+pub enum StmtKind {
    Return(Expr)
 }
 
 impl Stmt {
+   pub fn new(kind: StmtKind, span: Span) -> Self {
+      Stmt { kind, span }
+   }
+
    pub fn pretty_print(&self, indent_level: usize) {
-      match self {
-         Self::Return(e) => {
+      match &self.kind {
+         StmtKind::Return(e) => {
             println!("{:indent$}Return(", "", indent=indent_level*3);
             e.pretty_print(indent_level+1);
             println!("{:indent$})", "", indent=indent_level*3);
@@ -107,89 +528,405 @@ impl Stmt {
       }
    }
 
-   pub fn parse<'a>(token_stream: &mut impl Iterator<Item=&'a Token>) -> Result<Self, String> {
-      expect_token!(token_stream.next(), Token::Return, "Syntax Error: expected return");
-      let expression = Expr::parse(token_stream)?;
-      expect_token!(token_stream.next(), Token::Semicolon, "Syntax Error: expected a semicolon");
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      let start = token_stream.next_span();
+      expect_token!(token_stream.next(), TokenKind::Return, "Syntax Error: expected return");
+      let expression = Expr::parse(token_stream, ctx)?;
+      expect_token!(token_stream.next(), TokenKind::Semicolon, "Syntax Error: expected a semicolon");
 
-      Ok(Stmt::Return(expression))
+      Ok(Stmt::new(StmtKind::Return(expression), token_stream.span_from(start)))
    }
 }
 
+#[derive(Clone)]
 pub enum UnaryOp {
    Complement(Expr),
    Negate(Expr),
+   Plus(Expr),
+   Not(Expr),
 }
 
 impl UnaryOp {
    pub fn pretty_print(&self, indent_level: usize) {
-      match self {
-         Self::Complement(e) => {
-            println!("{:indent$}Complement(", "", indent=indent_level*3);
-            e.pretty_print(indent_level+1);
-            println!("{:indent$})", "", indent=indent_level*3);
-         },
-         Self::Negate(e) => {
-            println!("{:indent$}Negate(", "", indent=indent_level*3);
-            e.pretty_print(indent_level+1);
-            println!("{:indent$})", "", indent=indent_level*3);
-         }
-      }
+      let (name, e) = match self {
+         Self::Complement(e) => ("Complement", e),
+         Self::Negate(e) => ("Negate", e),
+         Self::Plus(e) => ("Plus", e),
+         Self::Not(e) => ("Not", e),
+      };
+      println!("{:indent$}{name}(", "", indent=indent_level*3, name=name);
+      e.pretty_print(indent_level+1);
+      println!("{:indent$})", "", indent=indent_level*3);
    }
 }
 
+#[derive(Clone)]
 pub enum BinaryOp {
    Add(Expr, Expr),
    Subtract(Expr, Expr),
    Multiply(Expr, Expr),
    Divide(Expr, Expr),
    Remainder(Expr, Expr),
+   ShiftLeft(Expr, Expr),
+   ShiftRight(Expr, Expr),
+   BitAnd(Expr, Expr),
+   BitOr(Expr, Expr),
+   BitXor(Expr, Expr),
+   LessThan(Expr, Expr),
+   LessEqual(Expr, Expr),
+   GreaterThan(Expr, Expr),
+   GreaterEqual(Expr, Expr),
+   Equal(Expr, Expr),
+   NotEqual(Expr, Expr),
+   And(Expr, Expr),
+   Or(Expr, Expr),
 }
 
-pub enum Expr {
-   Const(u64),
+/// Builds a binary operator node from its two operands.
+type BinaryOpConstructor = fn(Expr, Expr) -> BinaryOp;
+
+impl BinaryOp {
+   /// The binding power of a binary operator token, with the constructor for its node.
+   /// Higher numbers bind tighter; `?:` sits below all of these.
+   fn from_token(kind: &TokenKind) -> Option<(u8, BinaryOpConstructor)> {
+      match kind {
+         TokenKind::Asterisk => Some((50, BinaryOp::Multiply)),
+         TokenKind::ForwardSlash => Some((50, BinaryOp::Divide)),
+         TokenKind::Percent => Some((50, BinaryOp::Remainder)),
+         TokenKind::Plus => Some((45, BinaryOp::Add)),
+         TokenKind::Negate => Some((45, BinaryOp::Subtract)),
+         TokenKind::ShiftLeft => Some((40, BinaryOp::ShiftLeft)),
+         TokenKind::ShiftRight => Some((40, BinaryOp::ShiftRight)),
+         TokenKind::LessThan => Some((35, BinaryOp::LessThan)),
+         TokenKind::LessEqual => Some((35, BinaryOp::LessEqual)),
+         TokenKind::GreaterThan => Some((35, BinaryOp::GreaterThan)),
+         TokenKind::GreaterEqual => Some((35, BinaryOp::GreaterEqual)),
+         TokenKind::Equal => Some((30, BinaryOp::Equal)),
+         TokenKind::NotEqual => Some((30, BinaryOp::NotEqual)),
+         TokenKind::Ampersand => Some((25, BinaryOp::BitAnd)),
+         TokenKind::Caret => Some((20, BinaryOp::BitXor)),
+         TokenKind::Pipe => Some((15, BinaryOp::BitOr)),
+         TokenKind::LogicalAnd => Some((10, BinaryOp::And)),
+         TokenKind::LogicalOr => Some((5, BinaryOp::Or)),
+         _ => None,
+      }
+   }
+
+   pub fn operands(&self) -> (&Expr, &Expr) {
+      match self {
+         Self::Add(l, r) | Self::Subtract(l, r) | Self::Multiply(l, r) | Self::Divide(l, r) | Self::Remainder(l, r) |
+         Self::ShiftLeft(l, r) | Self::ShiftRight(l, r) | Self::BitAnd(l, r) | Self::BitOr(l, r) | Self::BitXor(l, r) |
+         Self::LessThan(l, r) | Self::LessEqual(l, r) | Self::GreaterThan(l, r) | Self::GreaterEqual(l, r) |
+         Self::Equal(l, r) | Self::NotEqual(l, r) | Self::And(l, r) | Self::Or(l, r) => (l, r),
+      }
+   }
+
+   fn name(&self) -> &'static str {
+      match self {
+         Self::Add(..) => "Add",
+         Self::Subtract(..) => "Subtract",
+         Self::Multiply(..) => "Multiply",
+         Self::Divide(..) => "Divide",
+         Self::Remainder(..) => "Remainder",
+         Self::ShiftLeft(..) => "ShiftLeft",
+         Self::ShiftRight(..) => "ShiftRight",
+         Self::BitAnd(..) => "BitAnd",
+         Self::BitOr(..) => "BitOr",
+         Self::BitXor(..) => "BitXor",
+         Self::LessThan(..) => "LessThan",
+         Self::LessEqual(..) => "LessEqual",
+         Self::GreaterThan(..) => "GreaterThan",
+         Self::GreaterEqual(..) => "GreaterEqual",
+         Self::Equal(..) => "Equal",
+         Self::NotEqual(..) => "NotEqual",
+         Self::And(..) => "And",
+         Self::Or(..) => "Or",
+      }
+   }
+
+   pub fn pretty_print(&self, indent_level: usize) {
+      let (left, right) = self.operands();
+      println!("{:indent$}{name}(", "", indent=indent_level*3, name=self.name());
+      left.pretty_print(indent_level+1);
+      right.pretty_print(indent_level+1);
+      println!("{:indent$})", "", indent=indent_level*3);
+   }
+}
+
+/// One `type-name: expr` or `default: expr` association of a generic selection.
+#[derive(Clone)]
+pub struct GenericAssociation {
+   pub assoc_type: Option<Type>,
+   pub expr: Expr,
+}
+
+#[derive(Clone)]
+pub struct Expr {
+   pub kind: ExprKind,
+   pub span: Span,
+}
+
+#[derive(Clone)]
+pub enum ExprKind {
+   Const(Constant),
    Unary(Box<UnaryOp>),
    Binary(Box<BinaryOp>),
-   Expr(Box<Expr>)
+   Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+   Cast(Box<Type>, Box<Expr>),
+   Sizeof(Box<Expr>),
+   SizeofType(Box<Type>),
+   Alignof(Box<Type>),
+   Generic(Box<Expr>, Vec<GenericAssociation>),
+   Nullptr,
+   /// A use of a constexpr object, with its initializer converted to the object's type.
+   Constexpr(String, Box<Expr>),
+   Paren(Box<Expr>)
 }
 
 impl Expr {
+   pub fn new(kind: ExprKind, span: Span) -> Self {
+      Expr { kind, span }
+   }
+
    pub fn pretty_print(&self, indent_level: usize) {
-      match self {
-         Self::Const(c) => {
-            println!("{:indent$}Constant({c})", "", indent=indent_level*3, c=c);
+      match &self.kind {
+         ExprKind::Const(c) => {
+            println!("{:indent$}Constant({c:?})", "", indent=indent_level*3, c=c);
          },
-         Self::Unary(op) => {
+         ExprKind::Unary(op) => {
             op.pretty_print(indent_level);
          },
-         Self::Expr(expr) => {
+         ExprKind::Paren(expr) => {
             expr.pretty_print(indent_level);
          },
-         Self::Binary(binop ) => ()
+         ExprKind::Binary(op) => {
+            op.pretty_print(indent_level);
+         },
+         ExprKind::Conditional(cond, then, otherwise) => {
+            println!("{:indent$}Conditional(", "", indent=indent_level*3);
+            cond.pretty_print(indent_level+1);
+            then.pretty_print(indent_level+1);
+            otherwise.pretty_print(indent_level+1);
+            println!("{:indent$})", "", indent=indent_level*3);
+         },
+         ExprKind::Cast(t, expr) => {
+            println!("{:indent$}Cast(type={t}", "", indent=indent_level*3, t=t);
+            expr.pretty_print(indent_level+1);
+            println!("{:indent$})", "", indent=indent_level*3);
+         },
+         ExprKind::Sizeof(expr) => {
+            println!("{:indent$}Sizeof(", "", indent=indent_level*3);
+            expr.pretty_print(indent_level+1);
+            println!("{:indent$})", "", indent=indent_level*3);
+         },
+         ExprKind::SizeofType(t) => {
+            println!("{:indent$}Sizeof(type={t})", "", indent=indent_level*3, t=t);
+         },
+         ExprKind::Alignof(t) => {
+            println!("{:indent$}Alignof(type={t})", "", indent=indent_level*3, t=t);
+         },
+         ExprKind::Generic(control, associations) => {
+            println!("{:indent$}Generic(", "", indent=indent_level*3);
+            control.pretty_print(indent_level+1);
+            for association in associations {
+               match &association.assoc_type {
+                  Some(t) => println!("{:indent$}{t}:", "", indent=(indent_level+1)*3, t=t),
+                  None => println!("{:indent$}default:", "", indent=(indent_level+1)*3),
+               }
+               association.expr.pretty_print(indent_level+2);
+            }
+            println!("{:indent$})", "", indent=indent_level*3);
+         },
+         ExprKind::Nullptr => {
+            println!("{:indent$}Nullptr", "", indent=indent_level*3);
+         },
+         ExprKind::Constexpr(name, _) => {
+            println!("{:indent$}Constexpr(\"{name}\")", "", indent=indent_level*3, name=name);
+         },
+      }
+   }
+
+   /// Parses a conditional expression; assignment and the comma operator are not supported yet.
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      Self::parse_binary(token_stream, ctx, 0)
+   }
+
+   /// Precedence climbing over the binary operators that bind at least as tightly as `min_precedence`.
+   fn parse_binary(token_stream: &mut TokenStream, ctx: &mut ParseContext, min_precedence: u8) -> Result<Self, CompileError> {
+      const CONDITIONAL_PRECEDENCE: u8 = 1;
+      let mut left = Self::parse_unary(token_stream, ctx)?;
+      loop {
+         match peek_kind(token_stream) {
+            Some(TokenKind::Question) if min_precedence <= CONDITIONAL_PRECEDENCE => {
+               token_stream.next();
+               let then = Self::parse(token_stream, ctx)?;
+               expect_token!(token_stream.next(), TokenKind::Colon, "Syntax Error: expected ':' in conditional expression");
+               // Right associative: the last operand may itself be a conditional expression.
+               let otherwise = Self::parse_binary(token_stream, ctx, CONDITIONAL_PRECEDENCE)?;
+               let span = left.span.to(&otherwise.span);
+               left = Expr::new(ExprKind::Conditional(Box::new(left), Box::new(then), Box::new(otherwise)), span);
+            },
+            Some(TokenKind::Increment | TokenKind::Decrement) => {
+               return Err(CompileError::parser(ErrorCode::Unsupported, "Increment and decrement operators are not yet supported"));
+            },
+            Some(TokenKind::PlusAssign) => {
+               return Err(CompileError::parser(ErrorCode::Unsupported, "Compound assignment is not yet supported"));
+            },
+            Some(kind) => {
+               let Some((precedence, make_op)) = BinaryOp::from_token(kind) else {
+                  break;
+               };
+               if precedence < min_precedence {
+                  break;
+               }
+               token_stream.next();
+               let right = Self::parse_binary(token_stream, ctx, precedence + 1)?;
+               let span = left.span.to(&right.span);
+               left = Expr::new(ExprKind::Binary(Box::new(make_op(left, right))), span);
+            },
+            None => break,
+         }
       }
+      Ok(left)
    }
 
-   pub fn parse<'a>(token_stream: &mut impl Iterator<Item=&'a Token>) -> Result<Self, String> {
-      match token_stream.next() {
-         Some(Token::Integer(v)) => {
-            Ok(Expr::Const(*v))
+   fn parse_unary(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      let start = token_stream.next_span();
+      let make_op: fn(Expr) -> UnaryOp = match peek_kind(token_stream) {
+         Some(TokenKind::BitFlip) => UnaryOp::Complement,
+         Some(TokenKind::Negate) => UnaryOp::Negate,
+         Some(TokenKind::Plus) => UnaryOp::Plus,
+         Some(TokenKind::LogicalNot) => UnaryOp::Not,
+         Some(TokenKind::Increment | TokenKind::Decrement) => {
+            return Err(CompileError::parser(ErrorCode::Unsupported, "Increment and decrement operators are not yet supported"));
          },
-         Some(Token::BitFlip) => {
-            let expr = Expr::parse(token_stream)?;
-            Ok(Expr::Unary(Box::new(UnaryOp::Complement(expr))))
+         Some(TokenKind::Sizeof) => {
+            token_stream.next();
+            if Self::starts_type_name(token_stream, ctx) {
+               token_stream.next();
+               let type_name = Declarator::parse_type_name(token_stream, ctx)?;
+               expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren after type name");
+               return Ok(Expr::new(ExprKind::SizeofType(Box::new(type_name)), token_stream.span_from(start)));
+            }
+            let operand = Self::parse_unary(token_stream, ctx)?;
+            return Ok(Expr::new(ExprKind::Sizeof(Box::new(operand)), token_stream.span_from(start)));
          },
-         Some(Token::Negate) => {
-            let expr = Expr::parse(token_stream)?;
-            Ok(Expr::Unary(Box::new(UnaryOp::Negate(expr))))
-         }
-         Some(Token::OpenParen) => {
-            let expr = Expr::parse(token_stream)?;
-            expect_token!(token_stream.next(), Token::CloseParen, "Syntax Error: expected closing paren after expression");
-            Ok(Expr::Expr(Box::new(expr)))
+         Some(TokenKind::Alignof) => {
+            token_stream.next();
+            expect_token!(token_stream.next(), TokenKind::OpenParen, "Syntax Error: expected open paren after _Alignof");
+            let type_name = Declarator::parse_type_name(token_stream, ctx)?;
+            expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren after type name");
+            return Ok(Expr::new(ExprKind::Alignof(Box::new(type_name)), token_stream.span_from(start)));
+         },
+         Some(TokenKind::OpenParen) if Self::starts_type_name(token_stream, ctx) => {
+            token_stream.next();
+            let type_name = Declarator::parse_type_name(token_stream, ctx)?;
+            expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren after type name");
+            let operand = Self::parse_unary(token_stream, ctx)?;
+            return Ok(Expr::new(ExprKind::Cast(Box::new(type_name), Box::new(operand)), token_stream.span_from(start)));
+         },
+         _ => return Self::parse_primary(token_stream, ctx),
+      };
+      token_stream.next();
+      let expr = Self::parse_unary(token_stream, ctx)?;
+      Ok(Expr::new(ExprKind::Unary(Box::new(make_op(expr))), token_stream.span_from(start)))
+   }
+
+   /// Whether the stream is at a `(` that opens a type name rather than an expression.
+   fn starts_type_name(token_stream: &TokenStream, ctx: &ParseContext) -> bool {
+      let mut lookahead = token_stream.clone();
+      matches!(lookahead.next().map(|t| &t.kind), Some(TokenKind::OpenParen))
+         && DeclSpecifiers::starts_declaration(&mut lookahead, ctx)
+   }
+
+   fn parse_primary(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      let start = token_stream.next_span();
+      let kind = match token_stream.next().map(|t| &t.kind) {
+         Some(TokenKind::Constant(c)) => ExprKind::Const(*c),
+         Some(TokenKind::OpenParen) => {
+            let expr = Expr::parse(token_stream, ctx)?;
+            expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren after expression");
+            ExprKind::Paren(Box::new(expr))
+         },
+         Some(TokenKind::Generic) => {
+            expect_token!(token_stream.next(), TokenKind::OpenParen, "Syntax Error: expected open paren after _Generic");
+            let control = Expr::parse(token_stream, ctx)?;
+            let mut associations: Vec<GenericAssociation> = Vec::new();
+            loop {
+               match token_stream.next().map(|t| &t.kind) {
+                  Some(TokenKind::Comma) => (),
+                  Some(TokenKind::CloseParen) if !associations.is_empty() => break,
+                  _ => {
+                     return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected a generic association"));
+                  }
+               }
+               let assoc_type = match peek_kind(token_stream) {
+                  Some(TokenKind::Default) => {
+                     token_stream.next();
+                     None
+                  },
+                  _ => Some(Declarator::parse_type_name(token_stream, ctx)?),
+               };
+               expect_token!(token_stream.next(), TokenKind::Colon, "Syntax Error: expected ':' in generic association");
+               let expr = Expr::parse(token_stream, ctx)?;
+               associations.push(GenericAssociation { assoc_type, expr });
+            }
+            ExprKind::Generic(Box::new(control), associations)
+         },
+         Some(TokenKind::Nullptr) => ExprKind::Nullptr,
+         // Variables are not supported yet, but constexpr objects have a known value.
+         Some(TokenKind::Identifier(name)) => match ctx.scope.constexpr(name) {
+            Some(value) => ExprKind::Constexpr(name.clone(), Box::new(value.clone())),
+            None => return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: Invalid Expression")),
+         },
+         Some(TokenKind::StringLiteral(..)) => {
+            return Err(CompileError::parser(ErrorCode::Unsupported, "String literals are not yet supported in expressions"));
          },
          _ => {
-            Err(String::from("Syntax Error: Invalid Expression"))
+            return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: Invalid Expression"));
          }
-      }
+      };
+      Ok(Expr::new(kind, token_stream.span_from(start)))
    }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::lexer::Lexer;
+
+   fn parse(source: &str) -> Result<Program, CompileError> {
+      let tokens = Lexer::new(source.to_string(), "test.c", Standard::C17).lex().expect("lexes");
+      Program::parse(&tokens, Standard::C17)
+   }
+
+   fn extent(span: &Span) -> (usize, Option<usize>, usize, Option<usize>) {
+      (span.line, span.column, span.end_line, span.end_column)
+   }
+
+   #[test]
+   fn block_items_have_spans() {
+      let source = "int main(void) {\n  int f(void), g(void);\n  return 1 +\n    2;\n}\nlong x;";
+      let Ok(Program::Program(decls)) = parse(source) else {
+         panic!("parses");
+      };
+      let Some(ExternalDecl::Function(main)) = decls.first() else {
+         panic!("main is a function");
+      };
+      assert_eq!(extent(main.body[0].span()), (2, Some(3), 2, Some(24)));
+      assert_eq!(extent(main.body[1].span()), (3, Some(3), 4, Some(7)));
+      let Some(ExternalDecl::Declaration(x)) = decls.last() else {
+         panic!("x is a declaration");
+      };
+      assert_eq!(extent(&x.span), (6, Some(1), 6, Some(8)));
+   }
+
+   #[test]
+   fn declaration_errors_are_located_at_the_declaration() {
+      let Err(error) = parse("int main(void) {\n   static int f(void), g; }") else {
+         panic!("static block-scope function should be rejected");
+      };
+      let span = error.diagnostic().span.as_ref().expect("located");
+      assert_eq!(extent(span), (2, Some(4), 2, Some(26)));
+   }
+}
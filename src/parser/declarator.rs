@@ -0,0 +1,357 @@
+use crate::cli::Standard;
+use crate::error::{CompileError, ErrorCode};
+use crate::lexer::token::TokenKind;
+use super::ast::Expr;
+use super::types::{BaseType, DeclSpecifiers, Param, Params, Qualifiers, StorageClass, Type};
+use super::{peek_kind, peek_nth_kind, skip_attributes, ParseContext, TokenStream};
+
+/// The shape of a declarator as written, before it is combined with the base type
+/// from the declaration specifiers.
+pub enum Declarator {
+   Name(Option<String>),
+   Pointer(Qualifiers, Box<Declarator>),
+   Array(Box<Declarator>, Option<Expr>),
+   Function(Box<Declarator>, Params),
+}
+
+impl Declarator {
+   /// Parses a declarator. With `allow_abstract` the identifier may be omitted, as in
+   /// parameter declarations like `int (*)(void)`.
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext, allow_abstract: bool) -> Result<Self, CompileError> {
+      if let Some(TokenKind::Asterisk) = peek_kind(token_stream) {
+         token_stream.next();
+         skip_attributes(token_stream, ctx)?;
+         let qualifiers = Qualifiers::parse(token_stream);
+         let inner = Declarator::parse(token_stream, ctx, allow_abstract)?;
+         return Ok(Declarator::Pointer(qualifiers, Box::new(inner)));
+      }
+
+      let mut declarator = match peek_kind(token_stream) {
+         Some(TokenKind::Identifier(name)) => {
+            token_stream.next();
+            skip_attributes(token_stream, ctx)?;
+            Declarator::Name(Some(name.clone()))
+         },
+         Some(TokenKind::OpenParen) if Self::is_nested(token_stream, ctx) => {
+            token_stream.next();
+            let inner = Declarator::parse(token_stream, ctx, allow_abstract)?;
+            expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren in declarator");
+            inner
+         },
+         _ if allow_abstract => Declarator::Name(None),
+         _ => return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected an identifier in declarator")),
+      };
+
+      loop {
+         match peek_kind(token_stream) {
+            Some(TokenKind::OpenBracket) => {
+               token_stream.next();
+               let size = match peek_kind(token_stream) {
+                  Some(TokenKind::CloseBracket) => None,
+                  _ => Some(Expr::parse(token_stream, ctx)?),
+               };
+               expect_token!(token_stream.next(), TokenKind::CloseBracket, "Syntax Error: expected closing bracket in array declarator");
+               declarator = Declarator::Array(Box::new(declarator), size);
+               skip_attributes(token_stream, ctx)?;
+            },
+            Some(TokenKind::OpenParen) => {
+               token_stream.next();
+               let params = Self::parse_params(token_stream, ctx)?;
+               declarator = Declarator::Function(Box::new(declarator), params);
+               skip_attributes(token_stream, ctx)?;
+            },
+            _ => break,
+         }
+      }
+
+      Ok(declarator)
+   }
+
+   /// Whether the derivation applied directly to the identifier is a function declarator,
+   /// as a function definition needs (C17 6.9.1p2): `f(void)` or `(*f(void))[3]`, but
+   /// not `(*f)(void)` or a plain `f` whose type comes from a typedef.
+   pub fn declares_function(&self) -> bool {
+      match self {
+         Declarator::Pointer(_, inner) | Declarator::Array(inner, _) | Declarator::Function(inner, _)
+            if !matches!(**inner, Declarator::Name(_)) => inner.declares_function(),
+         Declarator::Function(..) => true,
+         _ => false,
+      }
+   }
+
+   /// Decides whether a `(` at the start of a direct declarator opens a nested declarator
+   /// or, in an abstract declarator, the parameter list of a function type.
+   fn is_nested(token_stream: &TokenStream, ctx: &ParseContext) -> bool {
+      match peek_nth_kind(token_stream, 1) {
+         Some(TokenKind::Asterisk) | Some(TokenKind::OpenParen) | Some(TokenKind::OpenBracket) => true,
+         Some(TokenKind::Identifier(name)) => ctx.scope.typedef(name).is_none(),
+         _ => false,
+      }
+   }
+
+   /// Parses a parameter list after its opening paren, up to and including the closing one.
+   /// C23 drops unprototyped functions: empty parentheses mean `(void)` and identifier
+   /// lists are gone.
+   fn parse_params(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Params, CompileError> {
+      if let Some(TokenKind::CloseParen) = peek_kind(token_stream) {
+         token_stream.next();
+         if ctx.std >= Standard::C23 {
+            return Ok(Params::Prototype(Vec::new(), false));
+         }
+         return Ok(Params::Unspecified);
+      }
+      if let (Some(TokenKind::Void), Some(TokenKind::CloseParen)) = (peek_kind(token_stream), peek_nth_kind(token_stream, 1)) {
+         token_stream.next();
+         token_stream.next();
+         return Ok(Params::Prototype(Vec::new(), false));
+      }
+      if let Some(TokenKind::Identifier(name)) = peek_kind(token_stream) && ctx.scope.typedef(name).is_none() {
+         if ctx.std >= Standard::C23 {
+            let message = format!("Parameter \"{}\" has no type; identifier lists were removed in C23", name);
+            return Err(CompileError::parser(ErrorCode::NotInStandard, message).at(token_stream.next_span()));
+         }
+         return Self::parse_identifier_list(token_stream);
+      }
+
+      // Parameter names live in their own prototype scope.
+      ctx.scope.enter();
+      let params = Self::parse_param_list(token_stream, ctx);
+      ctx.scope.exit();
+      let params = params?;
+
+      expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren after parameters");
+      Ok(params)
+   }
+
+   fn parse_param_list(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Params, CompileError> {
+      let mut params: Vec<Param> = Vec::new();
+      let mut is_variadic = false;
+      loop {
+         if let Some(TokenKind::Ellipsis) = peek_kind(token_stream) {
+            token_stream.next();
+            if params.is_empty() {
+               return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: a named parameter is required before '...'"));
+            }
+            is_variadic = true;
+            break;
+         }
+
+         skip_attributes(token_stream, ctx)?;
+         let start = token_stream.next_span();
+         if !DeclSpecifiers::starts_declaration(token_stream, ctx) {
+            return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected a parameter declaration"));
+         }
+         let specifiers = DeclSpecifiers::parse(token_stream, ctx)?;
+         if !matches!(specifiers.storage_class, None | Some(StorageClass::Register)) {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid storage class for parameter"));
+         }
+         if specifiers.is_inline || specifiers.is_noreturn {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Function specifiers are not allowed on parameters"));
+         }
+         if specifiers.is_constexpr {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "constexpr is not allowed on parameters"));
+         }
+         if !specifiers.alignment.is_empty() {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "_Alignas is not allowed on parameters"));
+         }
+
+         let declarator = Declarator::parse(token_stream, ctx, true)?;
+         let (name, param_type) = declarator.apply(specifiers.base_type)?;
+         if param_type.has_identifier_list() {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Parameter names without types in a parameter declaration"));
+         }
+         if param_type.is_void() {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "'void' must be the only parameter"));
+         }
+         if let Some(name) = &name {
+            ctx.scope.declare(name, None);
+         }
+         let span = token_stream.span_from(start);
+         params.push(Param { name, param_type: Self::adjust_param_type(param_type.clone()), declared_type: param_type, span });
+
+         match peek_kind(token_stream) {
+            Some(TokenKind::Comma) => {
+               token_stream.next();
+            },
+            _ => break,
+         }
+      }
+      Ok(Params::Prototype(params, is_variadic))
+   }
+
+   /// Parses a K&R identifier list, giving each parameter int type until the
+   /// definition's declaration list says otherwise.
+   fn parse_identifier_list(token_stream: &mut TokenStream) -> Result<Params, CompileError> {
+      let mut params: Vec<Param> = Vec::new();
+      loop {
+         match token_stream.next().map(|t| &t.kind) {
+            Some(TokenKind::Identifier(name)) => {
+               if params.iter().any(|p| p.name.as_ref() == Some(name)) {
+                  return Err(CompileError::parser(ErrorCode::Redefinition, format!("Redefinition of parameter \"{}\"", name)));
+               }
+               let span = token_stream.previous_span().clone();
+               params.push(Param { name: Some(name.clone()), param_type: Type::Base(BaseType::Int), declared_type: Type::Base(BaseType::Int), span });
+            },
+            _ => {
+               return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected a parameter name"));
+            }
+         }
+         match token_stream.next().map(|t| &t.kind) {
+            Some(TokenKind::Comma) => (),
+            Some(TokenKind::CloseParen) => break,
+            _ => {
+               return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected closing paren after parameter names"));
+            }
+         }
+      }
+      Ok(Params::OldStyle(params))
+   }
+
+   /// Parses a type name, as in casts and `sizeof`: specifiers and qualifiers followed by
+   /// an abstract declarator.
+   pub fn parse_type_name(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Type, CompileError> {
+      let specifiers = DeclSpecifiers::parse(token_stream, ctx)?;
+      if specifiers.storage_class.is_some() || specifiers.is_inline || specifiers.is_noreturn || specifiers.is_constexpr
+         || !specifiers.alignment.is_empty() {
+         return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: only type specifiers and qualifiers are allowed in a type name"));
+      }
+      match Declarator::parse(token_stream, ctx, true)?.apply(specifiers.base_type)? {
+         (None, type_name) => Ok(type_name),
+         (Some(name), _) => Err(CompileError::parser(ErrorCode::SyntaxError, format!("Syntax Error: unexpected identifier \"{}\" in type name", name))),
+      }
+   }
+
+   /// Parameters declared as arrays or functions have pointer type (C17 6.7.6.3p7-8).
+   pub fn adjust_param_type(param_type: Type) -> Type {
+      match param_type {
+         Type::Array(element, _) => Type::Pointer(element),
+         Type::Function(..) => Type::Pointer(Box::new(param_type)),
+         _ => param_type,
+      }
+   }
+
+   /// Combines the declarator with the base type from the specifiers, giving the
+   /// declared name (if any) and its full type.
+   pub fn apply(self, base_type: Type) -> Result<(Option<String>, Type), CompileError> {
+      match self {
+         Declarator::Name(name) => Ok((name, base_type)),
+         Declarator::Pointer(qualifiers, inner) => {
+            inner.apply(Type::Pointer(Box::new(base_type)).qualify(qualifiers))
+         },
+         Declarator::Array(inner, size) => {
+            if base_type.is_function() {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid declarator: array of functions"));
+            }
+            if base_type.is_void() {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid declarator: array of void"));
+            }
+            inner.apply(Type::Array(Box::new(base_type), size))
+         },
+         Declarator::Function(inner, params) => {
+            match base_type.unqualified() {
+               Type::Function(..) => return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid declarator: function returning a function")),
+               Type::Array(..) => return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid declarator: function returning an array")),
+               _ => (),
+            }
+            inner.apply(Type::Function(Box::new(base_type), params))
+         },
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use crate::cli::Standard;
+   use crate::error::{CompileError, ErrorCode};
+   use crate::lexer::Lexer;
+   use crate::parser::ast::{ExternalDecl, Program};
+
+   fn parse(source: &str) -> Result<Program, CompileError> {
+      let tokens = Lexer::new(source.to_string(), "test.c", Standard::C17).lex().expect("lexes");
+      Program::parse(&tokens, Standard::C17)
+   }
+
+   /// The type of the last name declared in `source`, as the parser prints it.
+   fn declared_type(source: &str) -> String {
+      let Ok(Program::Program(decls)) = parse(source) else {
+         panic!("{} should parse", source);
+      };
+      match decls.last() {
+         Some(ExternalDecl::Declaration(d)) => d.declarators.last().expect("declarator").decl_type.to_string(),
+         Some(ExternalDecl::Function(f)) => f.func_type.to_string(),
+         _ => panic!("no declaration in {}", source),
+      }
+   }
+
+   fn error_code(source: &str) -> ErrorCode {
+      match parse(source) {
+         Ok(_) => panic!("{} should be rejected", source),
+         Err(e) => e.diagnostic().code,
+      }
+   }
+
+   #[test]
+   fn nested_declarators() {
+      assert_eq!(declared_type("int *a[3];"), "array[3] of pointer to int");
+      assert_eq!(declared_type("int (*a)[3];"), "pointer to array[3] of int");
+      assert_eq!(declared_type("int (*f)(void);"), "pointer to function(void) returning int");
+      assert_eq!(declared_type("int *(*f(long))[3];"), "function(long) returning pointer to array[3] of pointer to int");
+      assert_eq!(declared_type("int (*(*fp)(void))(char);"),
+         "pointer to function(void) returning pointer to function(char) returning int");
+      assert_eq!(declared_type("char *const *p;"), "pointer to const pointer to char");
+      assert_eq!(declared_type("int ((a));"), "int");
+      assert_eq!(error_code("int f(void)(void);"), ErrorCode::InvalidDeclaration);
+      assert_eq!(error_code("int f(void)[3];"), ErrorCode::InvalidDeclaration);
+      assert_eq!(error_code("int a[3](void);"), ErrorCode::InvalidDeclaration);
+      assert_eq!(error_code("void a[3];"), ErrorCode::InvalidDeclaration);
+   }
+
+   #[test]
+   fn abstract_declarators() {
+      assert_eq!(declared_type("int f(int (*)(void), int [3], int *[2], int (void));"),
+         "function(pointer to function(void) returning int, pointer to int, pointer to pointer to int, \
+          pointer to function(void) returning int) returning int");
+      assert_eq!(declared_type("int f(int (*)[4]);"), "function(pointer to array[4] of int) returning int");
+      assert_eq!(declared_type("long n = sizeof(int (*)[4]);"), "long");
+      assert_eq!(error_code("long n = sizeof(int x);"), ErrorCode::SyntaxError);
+      assert_eq!(error_code("int;"), ErrorCode::InvalidDeclaration);
+      assert_eq!(error_code("int *;"), ErrorCode::SyntaxError);
+   }
+
+   #[test]
+   fn typedef_scoping() {
+      assert_eq!(declared_type("typedef int *P; P a[2];"), "array[2] of pointer to int");
+      assert_eq!(declared_type("typedef int T; int f(void) { typedef long T; return 0; } T x;"), "int");
+      // A typedef name in parentheses is a parameter type, not a nested declarator.
+      assert_eq!(declared_type("typedef int T; int f(T);"), "function(int) returning int");
+      assert_eq!(declared_type("typedef int T; int f(int (T));"), "function(pointer to function(int) returning int) returning int");
+      // An ordinary identifier hides the typedef for the rest of its scope.
+      assert_eq!(error_code("typedef int T; int f(void) { int T; T x; return 0; }"), ErrorCode::SyntaxError);
+      assert_eq!(declared_type("typedef int T; int f(void) { int T; return 0; } T x;"), "int");
+      assert_eq!(error_code("typedef int T; int g(long T, T *p);"), ErrorCode::SyntaxError);
+      assert_eq!(declared_type("typedef int T; int g(long T); T y;"), "int");
+      assert_eq!(error_code("int f(void) { typedef int T; return 0; } T x;"), ErrorCode::SyntaxError);
+   }
+
+   #[test]
+   fn function_definitions_need_a_function_declarator() {
+      assert_eq!(error_code("typedef int F(void); F f { return 0; }"), ErrorCode::InvalidDeclaration);
+      assert_eq!(error_code("typedef int F(void); F (f) { return 0; }"), ErrorCode::InvalidDeclaration);
+      assert_eq!(declared_type("typedef int F(void); F f;"), "function(void) returning int");
+      assert_eq!(declared_type("typedef int F(void); int (*g(void))(void) { return 0; }"),
+         "function(void) returning pointer to function(void) returning int");
+      assert_eq!(declared_type("int (f)(void) { return 0; }"), "function(void) returning int");
+   }
+
+   #[test]
+   fn identifier_lists_are_gone_in_c23() {
+      let tokens = Lexer::new("int f(x, y) { return 0; }".to_string(), "test.c", Standard::C23).lex().expect("lexes");
+      let Err(error) = Program::parse(&tokens, Standard::C23) else {
+         panic!("identifier list should be rejected");
+      };
+      let diagnostic = error.diagnostic();
+      assert_eq!(diagnostic.code, ErrorCode::NotInStandard);
+      assert_eq!(diagnostic.span.as_ref().and_then(|s| s.column), Some(7));
+      assert_eq!(declared_type("int f(x, y) int x, y; { return 0; }"), "function(x, y) returning int");
+   }
+}
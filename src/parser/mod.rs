@@ -1,20 +1,158 @@
+/// Consumes a token of the expected kind, or fails with a syntax error located at the
+/// token found instead.
+macro_rules! expect_token {
+   ($actual_token:expr, $expected_token:path, $msg:literal) => {
+      match $actual_token {
+         Some($crate::lexer::token::Token { kind: $expected_token, .. }) => (),
+         actual => {
+            let error = $crate::error::CompileError::parser($crate::error::ErrorCode::SyntaxError, $msg);
+            return Err(match actual {
+               Some(token) => error.at(&token.span),
+               None => error,
+            });
+         }
+      }
+   };
+}
+
 pub mod ast;
+mod declarator;
+mod scope;
+pub mod types;
 
-use crate::lexer::token::Token;
+use crate::cli::Standard;
+use crate::error::{CompileError, ErrorCode};
+use crate::lexer::token::{Encoding, StringChar, Token, TokenKind};
+use crate::span::Span;
 use ast::Program;
+use scope::ScopeStack;
+
+/// A cursor over the tokens being parsed. It also remembers where it has been, so
+/// nodes can be given spans that end at the last token they consumed. Nothing is
+/// parsed from an empty stream, so there is always a token to take a span from.
+#[derive(Clone)]
+pub struct TokenStream<'a> {
+   tokens: &'a [Token],
+   next: usize,
+}
+
+impl<'a> TokenStream<'a> {
+   pub fn new(tokens: &'a [Token]) -> Self {
+      TokenStream { tokens, next: 0 }
+   }
+
+   pub fn peek(&self) -> Option<&'a Token> {
+      self.tokens.get(self.next)
+   }
+
+   /// The span of the last token consumed, or of the first one if nothing has been
+   /// consumed yet.
+   pub fn previous_span(&self) -> &'a Span {
+      &self.tokens[self.next.saturating_sub(1)].span
+   }
+
+   /// The span of the next token, or of the last one at the end of the input.
+   pub fn next_span(&self) -> &'a Span {
+      &self.tokens[self.next.min(self.tokens.len() - 1)].span
+   }
+
+   /// The span from `start` to the end of the last token consumed.
+   pub fn span_from(&self, start: &Span) -> Span {
+      start.to(self.previous_span())
+   }
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+   type Item = &'a Token;
+
+   fn next(&mut self) -> Option<&'a Token> {
+      let token = self.tokens.get(self.next)?;
+      self.next += 1;
+      Some(token)
+   }
+}
+
+/// State shared by the parse functions.
+pub struct ParseContext {
+   pub scope: ScopeStack,
+   pub std: Standard,
+}
+
+/// Kind of the next token, without consuming it.
+pub fn peek_kind<'a>(token_stream: &mut TokenStream<'a>) -> Option<&'a TokenKind> {
+   token_stream.peek().map(|t| &t.kind)
+}
+
+/// Kind of the token `n` places after the next one, without consuming anything.
+pub fn peek_nth_kind<'a>(token_stream: &TokenStream<'a>, n: usize) -> Option<&'a TokenKind> {
+   token_stream.clone().nth(n).map(|t| &t.kind)
+}
+
+/// Reads a run of adjacent string literals and concatenates them (C17 6.4.5p5). Unprefixed
+/// literals take the prefix of the others.
+pub fn parse_string_literals(token_stream: &mut TokenStream) -> Result<(Encoding, Vec<StringChar>), CompileError> {
+   let mut encoding: Option<Encoding> = None;
+   let mut chars: Vec<StringChar> = Vec::new();
+   while let Some(TokenKind::StringLiteral(next, next_chars)) = peek_kind(token_stream) {
+      token_stream.next();
+      encoding = match (encoding, *next) {
+         (None | Some(Encoding::Plain), next) => Some(next),
+         (Some(current), Encoding::Plain) => Some(current),
+         (Some(current), next) if current == next => Some(current),
+         _ => {
+            return Err(CompileError::parser(ErrorCode::InvalidLiteral, "Concatenation of string literals with different encoding prefixes"));
+         }
+      };
+      chars.extend_from_slice(next_chars);
+   }
+   encoding.map(|e| (e, chars)).ok_or_else(|| CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected a string literal"))
+}
+
+/// Skips any C23 attribute specifiers (`[[...]]`) at the front of the token stream,
+/// returning whether there were any. No attributes are acted on, which the standard
+/// allows. Earlier standards have no attribute syntax, so `[[` is rejected there.
+pub fn skip_attributes(token_stream: &mut TokenStream, ctx: &ParseContext) -> Result<bool, CompileError> {
+   let mut skipped = false;
+   while let (Some(TokenKind::OpenBracket), Some(TokenKind::OpenBracket)) = (peek_kind(token_stream), peek_nth_kind(token_stream, 1)) {
+      if ctx.std < Standard::C23 {
+         return Err(CompileError::parser(ErrorCode::NotInStandard, "Attribute specifiers are a C23 feature"));
+      }
+      token_stream.next();
+      token_stream.next();
+      // The attribute arguments may hold any balanced tokens.
+      let mut depth = 0;
+      loop {
+         match token_stream.next().map(|t| &t.kind) {
+            Some(TokenKind::CloseBracket) if depth == 0 => break,
+            Some(TokenKind::CloseParen | TokenKind::CloseBrace) if depth == 0 => {
+               return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: unbalanced tokens in attribute specifier"));
+            },
+            Some(TokenKind::OpenParen | TokenKind::OpenBracket | TokenKind::OpenBrace) => depth += 1,
+            Some(TokenKind::CloseParen | TokenKind::CloseBracket | TokenKind::CloseBrace) => depth -= 1,
+            Some(_) => (),
+            None => return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: unterminated attribute specifier")),
+         }
+      }
+      expect_token!(token_stream.next(), TokenKind::CloseBracket, "Syntax Error: expected ']]' after attributes");
+      skipped = true;
+   }
+   Ok(skipped)
+}
 
 pub struct Parser {
-   pp_tokens: Vec<Token>
+   pp_tokens: Vec<Token>,
+   std: Standard
 }
 
 impl Parser {
-   pub fn new(pp_tokens_: Vec<Token>) -> Self {
+   pub fn new(pp_tokens_: Vec<Token>, std_: Standard) -> Self {
       Parser {
-         pp_tokens: pp_tokens_
+         pp_tokens: pp_tokens_,
+         std: std_
       }
    }
 
-   pub fn parse(&mut self) -> Result<ast::Program, String> {
-      Program::parse(&self.pp_tokens)
+   pub fn parse(&mut self) -> Result<ast::Program, CompileError> {
+      Program::parse(&self.pp_tokens, self.std)
    }
-}
\ No newline at end of file
+}
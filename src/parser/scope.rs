@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use super::ast::Expr;
+use super::types::Type;
+
+/// What an identifier declared in some scope stands for, as far as the parser cares.
+enum Entry {
+   Ordinary,
+   Typedef(Type),
+   /// A C23 constexpr object, usable in constant expressions as its converted initializer.
+   Constexpr(Expr),
+}
+
+/// Block scopes of the identifiers declared so far. The parser needs this to tell a
+/// typedef name used as a type specifier apart from an ordinary identifier.
+pub struct ScopeStack {
+   scopes: Vec<HashMap<String, Entry>>
+}
+
+impl ScopeStack {
+   pub fn new() -> Self {
+      ScopeStack {
+         scopes: vec![HashMap::new()]
+      }
+   }
+
+   pub fn enter(&mut self) {
+      self.scopes.push(HashMap::new());
+   }
+
+   pub fn exit(&mut self) {
+      self.scopes.pop();
+   }
+
+   /// Declares `name` in the innermost scope, as a typedef for `typedef_type` if given
+   /// and as an ordinary identifier (shadowing any outer typedef) otherwise.
+   pub fn declare(&mut self, name: &str, typedef_type: Option<Type>) {
+      let entry = match typedef_type {
+         Some(t) => Entry::Typedef(t),
+         None => Entry::Ordinary,
+      };
+      self.insert(name, entry);
+   }
+
+   /// Declares `name` in the innermost scope as a constexpr object with the given value.
+   pub fn declare_constexpr(&mut self, name: &str, value: Expr) {
+      self.insert(name, Entry::Constexpr(value));
+   }
+
+   fn insert(&mut self, name: &str, entry: Entry) {
+      if let Some(scope) = self.scopes.last_mut() {
+         scope.insert(name.to_string(), entry);
+      }
+   }
+
+   /// The type `name` stands for, if the nearest declaration of it is a typedef.
+   pub fn typedef(&self, name: &str) -> Option<&Type> {
+      match self.lookup(name) {
+         Some(Entry::Typedef(t)) => Some(t),
+         _ => None,
+      }
+   }
+
+   /// The value of `name`, if the nearest declaration of it is a constexpr object.
+   pub fn constexpr(&self, name: &str) -> Option<&Expr> {
+      match self.lookup(name) {
+         Some(Entry::Constexpr(value)) => Some(value),
+         _ => None,
+      }
+   }
+
+   fn lookup(&self, name: &str) -> Option<&Entry> {
+      self.scopes.iter().rev().find_map(|scope| scope.get(name))
+   }
+}
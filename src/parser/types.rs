@@ -0,0 +1,492 @@
+use std::fmt;
+use crate::cli::Standard;
+use crate::error::{CompileError, ErrorCode};
+use crate::lexer::token::{Constant, TokenKind};
+use crate::semantic::const_eval;
+use crate::span::Span;
+use super::ast::{Expr, ExprKind};
+use super::declarator::Declarator;
+use super::{peek_kind, skip_attributes, ParseContext, TokenStream};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+   Typedef,
+   Extern,
+   Static,
+   Auto,
+   Register,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Qualifiers {
+   pub is_const: bool,
+   pub is_volatile: bool,
+   pub is_restrict: bool,
+}
+
+impl Qualifiers {
+   pub fn is_empty(&self) -> bool {
+      !(self.is_const || self.is_volatile || self.is_restrict)
+   }
+
+   /// Consumes any run of type qualifiers at the front of the token stream.
+   pub fn parse(token_stream: &mut TokenStream) -> Self {
+      let mut qualifiers = Qualifiers::default();
+      while let Some(kind) = peek_kind(token_stream) {
+         match kind {
+            TokenKind::Const => qualifiers.is_const = true,
+            TokenKind::Volatile => qualifiers.is_volatile = true,
+            TokenKind::Restrict => qualifiers.is_restrict = true,
+            _ => break,
+         }
+         token_stream.next();
+      }
+      qualifiers
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BaseType {
+   Void,
+   Bool,
+   Char,
+   SChar,
+   UChar,
+   Short,
+   UShort,
+   Int,
+   UInt,
+   Long,
+   ULong,
+   LongLong,
+   ULongLong,
+   Float,
+   Double,
+   LongDouble,
+   /// The C23 type of `nullptr`, spelled `typeof(nullptr)` or `nullptr_t`.
+   NullPtr,
+}
+
+#[derive(Clone)]
+pub enum Type {
+   Base(BaseType),
+   Qualified(Qualifiers, Box<Type>),
+   Pointer(Box<Type>),
+   Array(Box<Type>, Option<Expr>),
+   Function(Box<Type>, Params),
+}
+
+impl Type {
+   /// Applies `qualifiers` to the type, leaving it untouched when there are none.
+   pub fn qualify(self, qualifiers: Qualifiers) -> Type {
+      if qualifiers.is_empty() {
+         self
+      } else {
+         Type::Qualified(qualifiers, Box::new(self))
+      }
+   }
+
+   /// The type with any top-level qualifiers removed.
+   pub fn unqualified(&self) -> &Type {
+      match self {
+         Type::Qualified(_, t) => t.unqualified(),
+         _ => self,
+      }
+   }
+
+   pub fn is_function(&self) -> bool {
+      matches!(self.unqualified(), Type::Function(..))
+   }
+
+   pub fn is_void(&self) -> bool {
+      matches!(self.unqualified(), Type::Base(BaseType::Void))
+   }
+
+   /// True if an old-style identifier list appears anywhere in the type. Those are only
+   /// allowed on the declarator of a function definition.
+   pub fn has_identifier_list(&self) -> bool {
+      match self {
+         Type::Base(_) => false,
+         Type::Qualified(_, t) | Type::Pointer(t) | Type::Array(t, _) => t.has_identifier_list(),
+         Type::Function(ret, params) => {
+            matches!(params, Params::OldStyle(_)) || ret.has_identifier_list() || params.has_identifier_list()
+         },
+      }
+   }
+}
+
+#[derive(Clone)]
+pub enum Params {
+   /// A parameter type list; the flag is set for a trailing `, ...`.
+   Prototype(Vec<Param>, bool),
+   /// Empty parentheses, which say nothing about the parameters.
+   Unspecified,
+   /// A K&R identifier list. The types come from the declaration list of the definition.
+   OldStyle(Vec<Param>),
+}
+
+impl Params {
+   fn has_identifier_list(&self) -> bool {
+      match self {
+         Params::Prototype(params, _) | Params::OldStyle(params) => params.iter().any(|p| p.param_type.has_identifier_list()),
+         Params::Unspecified => false,
+      }
+   }
+}
+
+#[derive(Clone)]
+pub struct Param {
+   pub name: Option<String>,
+   /// The adjusted type, with arrays and functions turned into pointers.
+   pub param_type: Type,
+   /// The type as written, which keeps any array bound for the validator to check.
+   pub declared_type: Type,
+   pub span: Span,
+}
+
+impl fmt::Display for BaseType {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      let name = match self {
+         BaseType::Void => "void",
+         BaseType::Bool => "_Bool",
+         BaseType::Char => "char",
+         BaseType::SChar => "signed char",
+         BaseType::UChar => "unsigned char",
+         BaseType::Short => "short",
+         BaseType::UShort => "unsigned short",
+         BaseType::Int => "int",
+         BaseType::UInt => "unsigned int",
+         BaseType::Long => "long",
+         BaseType::ULong => "unsigned long",
+         BaseType::LongLong => "long long",
+         BaseType::ULongLong => "unsigned long long",
+         BaseType::Float => "float",
+         BaseType::Double => "double",
+         BaseType::LongDouble => "long double",
+         BaseType::NullPtr => "nullptr_t",
+      };
+      write!(f, "{}", name)
+   }
+}
+
+impl fmt::Display for Type {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         Type::Base(b) => write!(f, "{}", b),
+         Type::Qualified(q, t) => {
+            if q.is_const {
+               write!(f, "const ")?;
+            }
+            if q.is_volatile {
+               write!(f, "volatile ")?;
+            }
+            if q.is_restrict {
+               write!(f, "restrict ")?;
+            }
+            write!(f, "{}", t)
+         },
+         Type::Pointer(t) => write!(f, "pointer to {}", t),
+         Type::Array(t, size) => {
+            match size.as_ref().map(|e| &e.kind) {
+               Some(ExprKind::Const(Constant::Integer(n, _))) => write!(f, "array[{}] of {}", n, t),
+               Some(_) => write!(f, "array[expr] of {}", t),
+               None => write!(f, "array[] of {}", t),
+            }
+         },
+         Type::Function(ret, params) => {
+            write!(f, "function(")?;
+            match params {
+               Params::Prototype(params, is_variadic) => {
+                  if params.is_empty() && !is_variadic {
+                     write!(f, "void")?;
+                  }
+                  for (i, param) in params.iter().enumerate() {
+                     if i > 0 {
+                        write!(f, ", ")?;
+                     }
+                     write!(f, "{}", param.param_type)?;
+                  }
+                  if *is_variadic {
+                     write!(f, ", ...")?;
+                  }
+               },
+               Params::OldStyle(params) => {
+                  for (i, param) in params.iter().enumerate() {
+                     if i > 0 {
+                        write!(f, ", ")?;
+                     }
+                     write!(f, "{}", param.name.as_deref().unwrap_or_default())?;
+                  }
+               },
+               Params::Unspecified => (),
+            }
+            write!(f, ") returning {}", ret)
+         },
+      }
+   }
+}
+
+/// The type specifier keywords, in the order `resolve_type_specifiers` expects them sorted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TypeSpecifier {
+   Void,
+   Bool,
+   Char,
+   Short,
+   Int,
+   Long,
+   Float,
+   Double,
+   Signed,
+   Unsigned,
+}
+
+/// Maps a multiset of type specifier keywords to the type it names (C17 6.7.2p2).
+fn resolve_type_specifiers(mut specifiers: Vec<TypeSpecifier>) -> Result<BaseType, CompileError> {
+   use TypeSpecifier::*;
+
+   specifiers.sort();
+   let base_type = match specifiers.as_slice() {
+      [Void] => BaseType::Void,
+      [Bool] => BaseType::Bool,
+      [Char] => BaseType::Char,
+      [Char, Signed] => BaseType::SChar,
+      [Char, Unsigned] => BaseType::UChar,
+      [Short] | [Short, Int] | [Short, Signed] | [Short, Int, Signed] => BaseType::Short,
+      [Short, Unsigned] | [Short, Int, Unsigned] => BaseType::UShort,
+      [Int] | [Signed] | [Int, Signed] => BaseType::Int,
+      [Unsigned] | [Int, Unsigned] => BaseType::UInt,
+      [Long] | [Int, Long] | [Long, Signed] | [Int, Long, Signed] => BaseType::Long,
+      [Long, Unsigned] | [Int, Long, Unsigned] => BaseType::ULong,
+      [Long, Long] | [Int, Long, Long] | [Long, Long, Signed] | [Int, Long, Long, Signed] => BaseType::LongLong,
+      [Long, Long, Unsigned] | [Int, Long, Long, Unsigned] => BaseType::ULongLong,
+      [Float] => BaseType::Float,
+      [Double] => BaseType::Double,
+      [Long, Double] => BaseType::LongDouble,
+      [] => return Err(CompileError::parser(ErrorCode::SyntaxError, "Syntax Error: expected a type specifier")),
+      _ => return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid combination of type specifiers")),
+   };
+   Ok(base_type)
+}
+
+/// An `_Alignas` specifier, naming either a type or an alignment in bytes.
+#[derive(Clone)]
+pub enum AlignmentSpecifier {
+   Type(Type),
+   Expr(Expr),
+}
+
+#[derive(Clone)]
+pub struct DeclSpecifiers {
+   pub storage_class: Option<StorageClass>,
+   pub base_type: Type,
+   pub is_inline: bool,
+   pub is_noreturn: bool,
+   pub is_constexpr: bool,
+   pub alignment: Vec<AlignmentSpecifier>,
+}
+
+impl DeclSpecifiers {
+   /// Prints the storage class and function specifiers; the type is printed per declarator.
+   pub fn pretty_print(&self, indent_level: usize) {
+      if let Some(storage_class) = &self.storage_class {
+         println!("{:indent$}storage={s:?}", "", indent=indent_level*3, s=storage_class);
+      }
+      if self.is_inline {
+         println!("{:indent$}inline", "", indent=indent_level*3);
+      }
+      if self.is_noreturn {
+         println!("{:indent$}noreturn", "", indent=indent_level*3);
+      }
+      if self.is_constexpr {
+         println!("{:indent$}constexpr", "", indent=indent_level*3);
+      }
+      for alignment in &self.alignment {
+         match alignment {
+            AlignmentSpecifier::Type(t) => println!("{:indent$}alignas(type={t})", "", indent=indent_level*3, t=t),
+            AlignmentSpecifier::Expr(e) => {
+               println!("{:indent$}alignas(", "", indent=indent_level*3);
+               e.pretty_print(indent_level+1);
+               println!("{:indent$})", "", indent=indent_level*3);
+            },
+         }
+      }
+   }
+
+   /// True if the next token can begin a declaration.
+   pub fn starts_declaration(token_stream: &mut TokenStream, ctx: &ParseContext) -> bool {
+      match peek_kind(token_stream) {
+         Some(TokenKind::Identifier(name)) => ctx.scope.typedef(name).is_some(),
+         Some(kind) => Self::is_specifier_keyword(kind),
+         None => false,
+      }
+   }
+
+   fn is_specifier_keyword(kind: &TokenKind) -> bool {
+      matches!(kind,
+         TokenKind::Void | TokenKind::Bool | TokenKind::Char | TokenKind::Short | TokenKind::Int |
+         TokenKind::Long | TokenKind::Float | TokenKind::Double | TokenKind::Signed | TokenKind::Unsigned |
+         TokenKind::Typedef | TokenKind::Extern | TokenKind::Static | TokenKind::Auto | TokenKind::Register |
+         TokenKind::Const | TokenKind::Volatile | TokenKind::Restrict | TokenKind::Inline | TokenKind::Noreturn |
+         TokenKind::Alignas | TokenKind::Typeof | TokenKind::TypeofUnqual | TokenKind::Constexpr)
+   }
+
+   pub fn parse(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Self, CompileError> {
+      let mut specifiers: Vec<TypeSpecifier> = Vec::new();
+      let mut typedef_type: Option<Type> = None;
+      let mut storage_class: Option<StorageClass> = None;
+      let mut qualifiers = Qualifiers::default();
+      let mut is_inline = false;
+      let mut is_noreturn = false;
+      let mut is_constexpr = false;
+      let mut alignment: Vec<AlignmentSpecifier> = Vec::new();
+
+      while let Some(kind) = peek_kind(token_stream) {
+         let storage = match kind {
+            TokenKind::Typedef => Some(StorageClass::Typedef),
+            TokenKind::Extern => Some(StorageClass::Extern),
+            TokenKind::Static => Some(StorageClass::Static),
+            TokenKind::Auto => Some(StorageClass::Auto),
+            TokenKind::Register => Some(StorageClass::Register),
+            _ => None,
+         };
+         if storage.is_some() {
+            if storage_class.is_some() {
+               return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Multiple storage classes in declaration specifiers"));
+            }
+            storage_class = storage;
+            token_stream.next();
+            continue;
+         }
+
+         match kind {
+            TokenKind::Void => specifiers.push(TypeSpecifier::Void),
+            TokenKind::Bool => specifiers.push(TypeSpecifier::Bool),
+            TokenKind::Char => specifiers.push(TypeSpecifier::Char),
+            TokenKind::Short => specifiers.push(TypeSpecifier::Short),
+            TokenKind::Int => specifiers.push(TypeSpecifier::Int),
+            TokenKind::Long => specifiers.push(TypeSpecifier::Long),
+            TokenKind::Float => specifiers.push(TypeSpecifier::Float),
+            TokenKind::Double => specifiers.push(TypeSpecifier::Double),
+            TokenKind::Signed => specifiers.push(TypeSpecifier::Signed),
+            TokenKind::Unsigned => specifiers.push(TypeSpecifier::Unsigned),
+            TokenKind::Const => qualifiers.is_const = true,
+            TokenKind::Volatile => qualifiers.is_volatile = true,
+            TokenKind::Restrict => qualifiers.is_restrict = true,
+            TokenKind::Inline => is_inline = true,
+            TokenKind::Noreturn => is_noreturn = true,
+            TokenKind::Constexpr => is_constexpr = true,
+            TokenKind::Typeof | TokenKind::TypeofUnqual => {
+               if !specifiers.is_empty() || typedef_type.is_some() {
+                  return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid combination of type specifiers"));
+               }
+               let is_unqual = matches!(kind, TokenKind::TypeofUnqual);
+               token_stream.next();
+               let operand_type = Self::parse_typeof_operand(token_stream, ctx)?;
+               typedef_type = Some(if is_unqual { operand_type.unqualified().clone() } else { operand_type });
+               continue;
+            },
+            TokenKind::Alignas => {
+               token_stream.next();
+               alignment.push(Self::parse_alignment_specifier(token_stream, ctx)?);
+               continue;
+            },
+            // A typedef name is only a type specifier if no other type specifier has been
+            // seen; otherwise it is the identifier being declared.
+            TokenKind::Identifier(name) if specifiers.is_empty() && typedef_type.is_none() => {
+               match ctx.scope.typedef(name) {
+                  Some(t) => typedef_type = Some(t.clone()),
+                  None => break,
+               }
+            },
+            _ => break,
+         }
+         token_stream.next();
+      }
+      skip_attributes(token_stream, ctx)?;
+
+      let base_type = match typedef_type {
+         Some(t) if specifiers.is_empty() => t,
+         Some(_) => return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid combination of type specifiers")),
+         // C89 still allows the type specifier to be left out, meaning int.
+         None if specifiers.is_empty() && ctx.std == Standard::C89 => Type::Base(BaseType::Int),
+         None => Type::Base(resolve_type_specifiers(specifiers)?),
+      };
+      if qualifiers.is_restrict && !matches!(base_type.unqualified(), Type::Pointer(_)) {
+         return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "Invalid use of restrict: only pointer types can be restrict-qualified"));
+      }
+      // A constexpr object is implicitly const (C23 6.7.1p8).
+      if is_constexpr {
+         if matches!(storage_class, Some(StorageClass::Typedef) | Some(StorageClass::Extern)) {
+            return Err(CompileError::parser(ErrorCode::InvalidDeclaration, "constexpr cannot be combined with typedef or extern"));
+         }
+         qualifiers.is_const = true;
+      }
+
+      Ok(DeclSpecifiers {
+         storage_class,
+         base_type: base_type.qualify(qualifiers),
+         is_inline,
+         is_noreturn,
+         is_constexpr,
+         alignment,
+      })
+   }
+
+   /// Parses the parenthesized operand of `typeof` or `typeof_unqual`: a type name, or an
+   /// expression whose type is taken without evaluating it (C23 6.7.2.5).
+   fn parse_typeof_operand(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<Type, CompileError> {
+      expect_token!(token_stream.next(), TokenKind::OpenParen, "Syntax Error: expected open paren after typeof");
+      let operand_type = if Self::starts_declaration(token_stream, ctx) {
+         Declarator::parse_type_name(token_stream, ctx)?
+      } else {
+         let expr = Expr::parse(token_stream, ctx)?;
+         const_eval::type_of(&expr).map_err(|e| CompileError::parser(ErrorCode::NotConstant, e).at(&expr.span))?
+      };
+      expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren after typeof");
+      Ok(operand_type)
+   }
+
+   /// Parses the parenthesized operand of `_Alignas`.
+   fn parse_alignment_specifier(token_stream: &mut TokenStream, ctx: &mut ParseContext) -> Result<AlignmentSpecifier, CompileError> {
+      expect_token!(token_stream.next(), TokenKind::OpenParen, "Syntax Error: expected open paren after _Alignas");
+      let alignment = if Self::starts_declaration(token_stream, ctx) {
+         AlignmentSpecifier::Type(Declarator::parse_type_name(token_stream, ctx)?)
+      } else {
+         AlignmentSpecifier::Expr(Expr::parse(token_stream, ctx)?)
+      };
+      expect_token!(token_stream.next(), TokenKind::CloseParen, "Syntax Error: expected closing paren after _Alignas");
+      Ok(alignment)
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use TypeSpecifier::*;
+
+   fn resolve(specifiers: &[TypeSpecifier]) -> Result<BaseType, ErrorCode> {
+      resolve_type_specifiers(specifiers.to_vec()).map_err(|e| e.diagnostic().code)
+   }
+
+   #[test]
+   fn specifier_order_does_not_matter() {
+      assert_eq!(resolve(&[Unsigned, Long, Int, Long]), Ok(BaseType::ULongLong));
+      assert_eq!(resolve(&[Int, Short, Signed]), Ok(BaseType::Short));
+      assert_eq!(resolve(&[Double, Long]), Ok(BaseType::LongDouble));
+      assert_eq!(resolve(&[Unsigned, Char]), Ok(BaseType::UChar));
+      assert_eq!(resolve(&[Char]), Ok(BaseType::Char));
+      assert_eq!(resolve(&[Signed]), Ok(BaseType::Int));
+   }
+
+   #[test]
+   fn invalid_specifier_combinations() {
+      for specifiers in [
+         &[Long, Long, Long][..], &[Signed, Unsigned], &[Short, Long], &[Int, Int], &[Void, Int],
+         &[Unsigned, Double], &[Long, Float], &[Long, Long, Double], &[Signed, Bool], &[Char, Short],
+      ] {
+         assert_eq!(resolve(specifiers), Err(ErrorCode::InvalidDeclaration), "{:?}", specifiers);
+      }
+      assert_eq!(resolve(&[]), Err(ErrorCode::SyntaxError));
+   }
+}
@@ -1,10 +1,12 @@
 use std::fs;
-use std::io;
 use std::process::Command;
+use crate::cli::Standard;
+use crate::error::{CompileError, ErrorCode};
 
 pub struct Preprocessor {
    source_filename: String,
    pp_filename: String,
+   std: Standard,
 }
 
 impl Drop for Preprocessor {
@@ -14,22 +16,54 @@ impl Drop for Preprocessor {
 }
 
 impl Preprocessor {
-   pub fn new(source_filename_: &String) -> Self {
+   pub fn new(source_filename_: &str, std_: Standard) -> Self {
       Self {
-         source_filename: source_filename_.clone(),
-         pp_filename: source_filename_.replace(".c", "_pp.c")
+         source_filename: source_filename_.to_string(),
+         pp_filename: source_filename_.replace(".c", "_pp.c"),
+         std: std_
       }
    }
 
-   pub fn process(&self) -> Result<String, io::Error> {
-      let _ = Command::new("gcc")
+   pub fn process(&self) -> Result<String, CompileError> {
+      let output = Command::new("gcc")
           .arg("-E")
-          .arg("-P")
+          .arg(format!("-std={}", self.std.gcc_name()))
           .arg(&self.source_filename)
           .arg("-o")
           .arg(&self.pp_filename)
-          .output();
+          .output()?;
+      if !output.status.success() {
+         return Err(CompileError::from_gcc(ErrorCode::PreprocessorFailed, "Preprocessing with gcc -E", &output));
+      }
 
-      fs::read_to_string(&self.pp_filename)
+      Ok(fs::read_to_string(&self.pp_filename)?)
    }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::env;
+
+   fn preprocess(source: &str, std: Standard) -> String {
+      let path = env::temp_dir().join(format!("pp_test_{}_{}.c", std.gcc_name(), std::process::id()));
+      let path = path.to_str().expect("temporary path is UTF-8").to_string();
+      fs::write(&path, source).expect("writes the source");
+      let output = Preprocessor::new(&path, std).process();
+      _ = fs::remove_file(&path);
+      output.expect("preprocesses")
+   }
+
+   #[test]
+   fn keeps_line_markers() {
+      let output = preprocess("#define ONE 1\n\nint x = ONE;\n", Standard::C17);
+      let marker = output.lines().rev().find(|l| l.starts_with("# ")).expect("a line marker");
+      assert!(marker.contains("pp_test_"), "{}", marker);
+      assert!(output.lines().any(|l| l == "int x = 1;"), "{}", output);
+   }
+
+   #[test]
+   fn uses_the_gnu_dialect_of_the_standard() {
+      let output = preprocess("long v = __STDC_VERSION__; int os = linux + unix;\n", Standard::C11);
+      assert!(output.contains("long v = 201112L; int os = 1 + 1;"), "{}", output);
+   }
+}
@@ -0,0 +1,192 @@
+use crate::parser::ast::Expr;
+use crate::parser::types::{BaseType, Param, Params, Qualifiers, Type};
+use super::const_eval::array_length;
+
+/// Splits off the top-level qualifiers, merging any nested `Qualified` wrappers.
+fn split_qualifiers(t: &Type) -> (Qualifiers, &Type) {
+   match t {
+      Type::Qualified(q, inner) => {
+         let (inner_q, unqualified) = split_qualifiers(inner);
+         let merged = Qualifiers {
+            is_const: q.is_const || inner_q.is_const,
+            is_volatile: q.is_volatile || inner_q.is_volatile,
+            is_restrict: q.is_restrict || inner_q.is_restrict,
+         };
+         (merged, unqualified)
+      },
+      _ => (Qualifiers::default(), t),
+   }
+}
+
+fn array_size(size: &Option<Expr>) -> Option<u64> {
+   size.as_ref().and_then(|size| array_length(size).ok())
+}
+
+/// The type after the default argument promotions (C17 6.5.2.2p6).
+fn promote(t: &Type) -> Type {
+   match t.unqualified() {
+      Type::Base(BaseType::Bool | BaseType::Char | BaseType::SChar | BaseType::UChar | BaseType::Short | BaseType::UShort) => Type::Base(BaseType::Int),
+      Type::Base(BaseType::Float) => Type::Base(BaseType::Double),
+      unqualified => unqualified.clone(),
+   }
+}
+
+/// Type compatibility as defined by C17 6.2.7.
+pub fn compatible(a: &Type, b: &Type) -> bool {
+   let (qa, a) = split_qualifiers(a);
+   let (qb, b) = split_qualifiers(b);
+   if qa != qb {
+      return false;
+   }
+
+   match (a, b) {
+      (Type::Base(x), Type::Base(y)) => x == y,
+      (Type::Pointer(x), Type::Pointer(y)) => compatible(x, y),
+      (Type::Array(x, size_x), Type::Array(y, size_y)) => {
+         let same_size = match (array_size(size_x), array_size(size_y)) {
+            (Some(m), Some(n)) => m == n,
+            _ => true,
+         };
+         same_size && compatible(x, y)
+      },
+      (Type::Function(ret_x, params_x), Type::Function(ret_y, params_y)) => {
+         compatible(ret_x, ret_y) && params_compatible(params_x, params_y)
+      },
+      _ => false,
+   }
+}
+
+/// Parameter compatibility for function types (C17 6.7.6.3p15). Top-level qualifiers on
+/// parameters do not take part.
+fn params_compatible(x: &Params, y: &Params) -> bool {
+   match (x, y) {
+      (Params::Prototype(px, variadic_x), Params::Prototype(py, variadic_y)) => {
+         variadic_x == variadic_y && px.len() == py.len()
+            && px.iter().zip(py).all(|(p, q)| compatible(p.param_type.unqualified(), q.param_type.unqualified()))
+      },
+      // Without a parameter list, the prototype must be callable with promoted arguments.
+      (Params::Prototype(params, is_variadic), Params::Unspecified) | (Params::Unspecified, Params::Prototype(params, is_variadic)) => {
+         !is_variadic && params.iter().all(|p| compatible(p.param_type.unqualified(), &promote(&p.param_type)))
+      },
+      // Against an old-style definition, the prototype must match the promoted parameter types.
+      (Params::Prototype(params, is_variadic), Params::OldStyle(old)) | (Params::OldStyle(old), Params::Prototype(params, is_variadic)) => {
+         !is_variadic && params.len() == old.len()
+            && params.iter().zip(old).all(|(p, o)| compatible(p.param_type.unqualified(), &promote(&o.param_type)))
+      },
+      (Params::OldStyle(px), Params::OldStyle(py)) => px.len() == py.len(),
+      _ => true,
+   }
+}
+
+/// The composite of two compatible types (C17 6.2.7p3): whichever carries more
+/// information about array sizes and parameters wins.
+pub fn composite(a: &Type, b: &Type) -> Type {
+   match (a, b) {
+      (Type::Qualified(q, x), Type::Qualified(_, y)) => Type::Qualified(*q, Box::new(composite(x, y))),
+      (Type::Pointer(x), Type::Pointer(y)) => Type::Pointer(Box::new(composite(x, y))),
+      (Type::Array(x, size_x), Type::Array(y, size_y)) => {
+         let size = if array_size(size_x).is_some() { size_x } else { size_y };
+         Type::Array(Box::new(composite(x, y)), size.clone())
+      },
+      (Type::Function(ret_x, params_x), Type::Function(ret_y, params_y)) => {
+         let params = match (params_x, params_y) {
+            // Both prototypes: the composite of each pair of parameters (C17 6.2.7p3).
+            (Params::Prototype(px, is_variadic), Params::Prototype(py, _)) => {
+               let params = px.iter().zip(py).map(|(p, q)| Param {
+                  param_type: composite(&p.param_type, &q.param_type),
+                  ..p.clone()
+               });
+               Params::Prototype(params.collect(), *is_variadic)
+            },
+            (Params::Prototype(..), _) => params_x.clone(),
+            (_, Params::Prototype(..)) => params_y.clone(),
+            (Params::OldStyle(_), _) => params_x.clone(),
+            _ => params_y.clone(),
+         };
+         Type::Function(Box::new(composite(ret_x, ret_y)), params)
+      },
+      _ => a.clone(),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::cli::Standard;
+   use crate::lexer::Lexer;
+   use crate::parser::ast::{ExternalDecl, Program};
+
+   /// The type of the last name declared or defined in `source`, parsed as C17.
+   fn declared(source: &str) -> Type {
+      let tokens = Lexer::new(source.to_string(), "test.c", Standard::C17).lex().expect("lexes");
+      let Ok(Program::Program(decls)) = Program::parse(&tokens, Standard::C17) else {
+         panic!("{} should parse", source);
+      };
+      match decls.last() {
+         Some(ExternalDecl::Declaration(d)) => d.declarators.last().expect("declarator").decl_type.clone(),
+         Some(ExternalDecl::Function(f)) => f.func_type.clone(),
+         _ => panic!("no declaration in {}", source),
+      }
+   }
+
+   fn compatible_decls(a: &str, b: &str) -> bool {
+      let (a, b) = (declared(a), declared(b));
+      let result = compatible(&a, &b);
+      assert_eq!(result, compatible(&b, &a), "compatibility of {} and {} is not symmetric", a, b);
+      result
+   }
+
+   #[test]
+   fn prototypes() {
+      assert!(compatible_decls("int f(int, long);", "int f(int a, long b);"));
+      assert!(compatible_decls("int f(const int);", "int f(int);"));
+      assert!(compatible_decls("int f(int a[3]);", "int f(int *);"));
+      assert!(compatible_decls("int f(int, ...);", "int f(int, ...);"));
+      assert!(!compatible_decls("int f(int);", "int f(long);"));
+      assert!(!compatible_decls("int f(int);", "int f(int, int);"));
+      assert!(!compatible_decls("int f(int);", "int f(int, ...);"));
+      assert!(!compatible_decls("int f(int *);", "int f(const int *);"));
+      assert!(!compatible_decls("int f(void);", "long f(void);"));
+   }
+
+   #[test]
+   fn prototypes_against_unspecified_parameters() {
+      assert!(compatible_decls("int f(int, double);", "int f();"));
+      assert!(!compatible_decls("int f(char);", "int f();"));
+      assert!(!compatible_decls("int f(float);", "int f();"));
+      assert!(!compatible_decls("int f(int, ...);", "int f();"));
+      assert!(compatible_decls("int f();", "int f();"));
+   }
+
+   #[test]
+   fn prototypes_against_old_style_definitions() {
+      assert!(compatible_decls("int f(int, double);", "int f(a, b) int a; float b; { return 0; }"));
+      assert!(compatible_decls("int f(int);", "int f(c) char c; { return 0; }"));
+      assert!(!compatible_decls("int f(char);", "int f(c) char c; { return 0; }"));
+      assert!(!compatible_decls("int f(int, int);", "int f(a) int a; { return 0; }"));
+      assert!(!compatible_decls("int f(int, ...);", "int f(a) int a; { return 0; }"));
+      assert!(compatible_decls("int f();", "int f(a) int a; { return 0; }"));
+   }
+
+   #[test]
+   fn arrays_and_qualifiers() {
+      assert!(compatible_decls("int a[3];", "int a[1 + 2];"));
+      assert!(compatible_decls("int a[];", "int a[3];"));
+      assert!(!compatible_decls("int a[3];", "int a[4];"));
+      assert!(!compatible_decls("int a[3];", "long a[3];"));
+      assert!(!compatible_decls("const int x;", "int x;"));
+      assert!(compatible_decls("const volatile int x;", "volatile const int x;"));
+   }
+
+   #[test]
+   fn composite_types() {
+      let composite_of = |a: &str, b: &str| composite(&declared(a), &declared(b)).to_string();
+      assert_eq!(composite_of("int a[];", "int a[3];"), "array[3] of int");
+      assert_eq!(composite_of("int a[3];", "int a[];"), "array[3] of int");
+      assert_eq!(composite_of("int (*p)[];", "int (*p)[5];"), "pointer to array[5] of int");
+      assert_eq!(composite_of("int a[][2];", "int a[4][];"), "array[4] of array[2] of int");
+      assert_eq!(composite_of("int f();", "int f(long);"), "function(long) returning int");
+      assert_eq!(composite_of("int f(a) int a; { return 0; }", "int f();"), "function(a) returning int");
+      assert_eq!(composite_of("int f(int (*)[]);", "int f(int (*)[2]);"), "function(pointer to array[2] of int) returning int");
+   }
+}
@@ -0,0 +1,776 @@
+use crate::lexer::token::{Constant, FloatType, IntType};
+use crate::parser::ast::{BinaryOp, Expr, ExprKind, GenericAssociation, UnaryOp};
+use crate::parser::types::{BaseType, Type};
+use super::compat::compatible;
+
+/// The value of an arithmetic constant expression, together with its type.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+   Integer(i128, BaseType),
+   Floating(f64, BaseType),
+}
+
+impl Value {
+   pub fn base_type(&self) -> BaseType {
+      match self {
+         Value::Integer(_, t) | Value::Floating(_, t) => *t,
+      }
+   }
+
+   fn is_nonzero(&self) -> bool {
+      match self {
+         Value::Integer(n, _) => *n != 0,
+         Value::Floating(f, _) => *f != 0.0,
+      }
+   }
+}
+
+/// Width in bits and signedness of an integer type on x86-64 (LP64, plain char signed).
+fn integer_layout(t: BaseType) -> Option<(u32, bool)> {
+   match t {
+      BaseType::Bool => Some((1, false)),
+      BaseType::Char | BaseType::SChar => Some((8, true)),
+      BaseType::UChar => Some((8, false)),
+      BaseType::Short => Some((16, true)),
+      BaseType::UShort => Some((16, false)),
+      BaseType::Int => Some((32, true)),
+      BaseType::UInt => Some((32, false)),
+      BaseType::Long | BaseType::LongLong => Some((64, true)),
+      BaseType::ULong | BaseType::ULongLong => Some((64, false)),
+      _ => None,
+   }
+}
+
+fn is_integer(t: BaseType) -> bool {
+   integer_layout(t).is_some()
+}
+
+fn is_signed(t: BaseType) -> bool {
+   matches!(integer_layout(t), Some((_, true)))
+}
+
+/// Smallest and largest values of an integer type.
+fn range(t: BaseType) -> (i128, i128) {
+   match integer_layout(t) {
+      Some((bits, true)) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+      Some((bits, false)) => (0, (1 << bits) - 1),
+      None => (0, 0),
+   }
+}
+
+/// Integer conversion rank (C17 6.3.1.1p1).
+fn rank(t: BaseType) -> u8 {
+   match t {
+      BaseType::Bool => 0,
+      BaseType::Char | BaseType::SChar | BaseType::UChar => 1,
+      BaseType::Short | BaseType::UShort => 2,
+      BaseType::Int | BaseType::UInt => 3,
+      BaseType::Long | BaseType::ULong => 4,
+      _ => 5,
+   }
+}
+
+fn float_rank(t: BaseType) -> u8 {
+   match t {
+      BaseType::Float => 0,
+      BaseType::Double => 1,
+      _ => 2,
+   }
+}
+
+/// The integer promotions: every type narrower than int fits in int.
+fn promote(t: BaseType) -> BaseType {
+   if is_integer(t) && rank(t) < rank(BaseType::Int) { BaseType::Int } else { t }
+}
+
+fn to_unsigned(t: BaseType) -> BaseType {
+   match t {
+      BaseType::Int => BaseType::UInt,
+      BaseType::Long => BaseType::ULong,
+      BaseType::LongLong => BaseType::ULongLong,
+      _ => t,
+   }
+}
+
+/// The common type of the usual arithmetic conversions (C17 6.3.1.8).
+fn common_type(a: BaseType, b: BaseType) -> BaseType {
+   if !is_integer(a) || !is_integer(b) {
+      return match (is_integer(a), is_integer(b)) {
+         (true, _) => b,
+         (_, true) => a,
+         _ => if float_rank(a) >= float_rank(b) { a } else { b },
+      };
+   }
+
+   let (a, b) = (promote(a), promote(b));
+   if a == b {
+      return a;
+   }
+   if is_signed(a) == is_signed(b) {
+      return if rank(a) >= rank(b) { a } else { b };
+   }
+   let (signed, unsigned) = if is_signed(a) { (a, b) } else { (b, a) };
+   if rank(unsigned) >= rank(signed) {
+      unsigned
+   } else if range(signed).1 >= range(unsigned).1 {
+      signed
+   } else {
+      to_unsigned(signed)
+   }
+}
+
+/// Reduces `n` modulo 2^N into the range of an integer type.
+fn wrap(n: i128, t: BaseType) -> i128 {
+   let (bits, signed) = integer_layout(t).unwrap_or((64, true));
+   let modulus = 1i128 << bits;
+   let m = n.rem_euclid(modulus);
+   if signed && m > range(t).1 { m - modulus } else { m }
+}
+
+/// The result of integer arithmetic in type `t`: unsigned types wrap, signed types
+/// must not overflow when the operation is evaluated.
+fn integer_result(n: i128, t: BaseType, evaluated: bool) -> Result<Value, String> {
+   let (min, max) = range(t);
+   if evaluated && is_signed(t) && (n < min || n > max) {
+      return Err(String::from("Integer overflow in constant expression"));
+   }
+   Ok(Value::Integer(wrap(n, t), t))
+}
+
+fn floating_result(f: f64, t: BaseType) -> Value {
+   match t {
+      BaseType::Float => Value::Floating(f as f32 as f64, t),
+      _ => Value::Floating(f, t),
+   }
+}
+
+fn convert(value: Value, t: BaseType, evaluated: bool) -> Result<Value, String> {
+   // nullptr_t converts only to itself and bool (C23 6.3.2.4).
+   let from = value.base_type();
+   if (from == BaseType::NullPtr) != (t == BaseType::NullPtr) && t != BaseType::Bool {
+      return Err(format!("Cannot convert {} to {}", from, t));
+   }
+   if t == BaseType::NullPtr {
+      return Ok(value);
+   }
+   if t == BaseType::Bool {
+      return Ok(Value::Integer(value.is_nonzero() as i128, t));
+   }
+   match value {
+      Value::Integer(n, _) if is_integer(t) => Ok(Value::Integer(wrap(n, t), t)),
+      Value::Integer(n, _) => Ok(floating_result(n as f64, t)),
+      Value::Floating(f, _) if is_integer(t) => {
+         // max itself may round up to the next power of two as a double, so compare
+         // against that power of two, which is exact, as an exclusive bound.
+         let (min, max) = range(t);
+         let truncated = f.trunc();
+         if !(truncated >= min as f64 && truncated < (max + 1) as f64) {
+            if evaluated {
+               return Err(format!("Floating value out of range in conversion to {}", t));
+            }
+            return Ok(Value::Integer(0, t));
+         }
+         Ok(Value::Integer(truncated as i128, t))
+      },
+      Value::Floating(f, _) => Ok(floating_result(f, t)),
+   }
+}
+
+/// Rejects nullptr as the operand of an arithmetic or bitwise operator.
+fn arithmetic_operand(value: Value) -> Result<Value, String> {
+   if value.base_type() == BaseType::NullPtr {
+      return Err(String::from("Invalid operand of type nullptr_t"));
+   }
+   Ok(value)
+}
+
+/// A null pointer constant: nullptr, or an integer constant zero.
+fn is_null_constant(value: Value) -> bool {
+   match value {
+      Value::Integer(n, t) => t == BaseType::NullPtr || (is_integer(t) && n == 0),
+      Value::Floating(..) => false,
+   }
+}
+
+fn promote_value(value: Value) -> Value {
+   match value {
+      Value::Integer(n, t) => Value::Integer(n, promote(t)),
+      floating => floating,
+   }
+}
+
+fn constant_value(c: &Constant) -> Value {
+   match c {
+      Constant::Integer(n, t) => {
+         let base = match t {
+            IntType::Bool => BaseType::Bool,
+            IntType::Int => BaseType::Int,
+            IntType::UInt => BaseType::UInt,
+            IntType::UShort => BaseType::UShort,
+            IntType::Long => BaseType::Long,
+            IntType::ULong => BaseType::ULong,
+            IntType::LongLong => BaseType::LongLong,
+            IntType::ULongLong => BaseType::ULongLong,
+         };
+         Value::Integer(wrap(*n as i128, base), base)
+      },
+      Constant::Floating(f, t) => {
+         let base = match t {
+            FloatType::Float => BaseType::Float,
+            FloatType::Double => BaseType::Double,
+            FloatType::LongDouble => BaseType::LongDouble,
+         };
+         Value::Floating(*f, base)
+      },
+   }
+}
+
+/// Evaluates an arithmetic constant expression. Operands that are not evaluated, like the
+/// untaken arm of `?:` or the operand of `sizeof`, only contribute their type, so faults
+/// such as division by zero are not reported for them.
+fn eval(expr: &Expr, evaluated: bool) -> Result<Value, String> {
+   match &expr.kind {
+      ExprKind::Const(c) => Ok(constant_value(c)),
+      ExprKind::Nullptr => Ok(Value::Integer(0, BaseType::NullPtr)),
+      ExprKind::Constexpr(_, value) => eval(value, evaluated),
+      ExprKind::Paren(e) => eval(e, evaluated),
+      ExprKind::Unary(op) => eval_unary(op, evaluated),
+      ExprKind::Binary(op) => eval_binary(op, evaluated),
+      ExprKind::Conditional(cond, then, otherwise) => {
+         let take_then = eval(cond, evaluated)?.is_nonzero();
+         let then = eval(then, evaluated && take_then)?;
+         let otherwise = eval(otherwise, evaluated && !take_then)?;
+         if (then.base_type() == BaseType::NullPtr) != (otherwise.base_type() == BaseType::NullPtr) {
+            return Err(format!("Invalid operands of types {} and {} to ?:", then.base_type(), otherwise.base_type()));
+         }
+         let t = common_type(then.base_type(), otherwise.base_type());
+         convert(if take_then { then } else { otherwise }, t, evaluated)
+      },
+      ExprKind::Cast(t, e) => {
+         let value = eval(e, evaluated)?;
+         match t.unqualified() {
+            Type::Base(b) if *b != BaseType::Void => convert(value, *b, evaluated),
+            _ => Err(format!("Cast to {} in a constant expression", t)),
+         }
+      },
+      ExprKind::Sizeof(e) => {
+         let operand = eval(e, false)?;
+         Ok(Value::Integer(size_of(&Type::Base(operand.base_type()))? as i128, BaseType::ULong))
+      },
+      ExprKind::SizeofType(t) => Ok(Value::Integer(size_of(t)? as i128, BaseType::ULong)),
+      ExprKind::Alignof(t) => Ok(Value::Integer(align_of(t)? as i128, BaseType::ULong)),
+      ExprKind::Generic(control, associations) => eval(select_generic(control, associations)?, evaluated),
+   }
+}
+
+/// Picks the association of a generic selection whose type is compatible with the type
+/// of the controlling expression, which is itself not evaluated (C17 6.5.1.1).
+fn select_generic<'a>(control: &Expr, associations: &'a [GenericAssociation]) -> Result<&'a Expr, String> {
+   let control_type = Type::Base(eval(control, false)?.base_type());
+   let mut selected: Option<&Expr> = None;
+   let mut default: Option<&Expr> = None;
+   for (i, association) in associations.iter().enumerate() {
+      let Some(assoc_type) = &association.assoc_type else {
+         if default.is_some() {
+            return Err(String::from("Duplicate default association in _Generic"));
+         }
+         default = Some(&association.expr);
+         continue;
+      };
+      if size_of(assoc_type).is_err() {
+         return Err(format!("_Generic association has type {}, which is not a complete object type", assoc_type));
+      }
+      let is_duplicate = associations[..i].iter()
+         .any(|other| other.assoc_type.as_ref().is_some_and(|t| compatible(t, assoc_type)));
+      if is_duplicate {
+         return Err(format!("_Generic has more than one association compatible with {}", assoc_type));
+      }
+      if compatible(&control_type, assoc_type) {
+         selected = Some(&association.expr);
+      }
+   }
+   selected.or(default)
+      .ok_or_else(|| format!("_Generic selector of type {} is not compatible with any association", control_type))
+}
+
+fn eval_unary(op: &UnaryOp, evaluated: bool) -> Result<Value, String> {
+   match op {
+      UnaryOp::Plus(e) => Ok(promote_value(arithmetic_operand(eval(e, evaluated)?)?)),
+      UnaryOp::Negate(e) => match promote_value(arithmetic_operand(eval(e, evaluated)?)?) {
+         Value::Integer(n, t) => integer_result(-n, t, evaluated),
+         Value::Floating(f, t) => Ok(Value::Floating(-f, t)),
+      },
+      UnaryOp::Complement(e) => match promote_value(arithmetic_operand(eval(e, evaluated)?)?) {
+         Value::Integer(n, t) => Ok(Value::Integer(wrap(!n, t), t)),
+         Value::Floating(..) => Err(String::from("Operand of '~' must have integer type")),
+      },
+      UnaryOp::Not(e) => Ok(Value::Integer(!eval(e, evaluated)?.is_nonzero() as i128, BaseType::Int)),
+   }
+}
+
+fn eval_binary(op: &BinaryOp, evaluated: bool) -> Result<Value, String> {
+   let (left, right) = op.operands();
+
+   // The logical operators do not evaluate their right operand once the result is known.
+   match op {
+      BinaryOp::And(..) => {
+         let l = eval(left, evaluated)?.is_nonzero();
+         let r = eval(right, evaluated && l)?.is_nonzero();
+         return Ok(Value::Integer((l && r) as i128, BaseType::Int));
+      },
+      BinaryOp::Or(..) => {
+         let l = eval(left, evaluated)?.is_nonzero();
+         let r = eval(right, evaluated && !l)?.is_nonzero();
+         return Ok(Value::Integer((l || r) as i128, BaseType::Int));
+      },
+      _ => (),
+   }
+
+   let l = eval(left, evaluated)?;
+   let r = eval(right, evaluated)?;
+
+   // nullptr only compares for equality with null pointer constants, and is always equal to them.
+   if l.base_type() == BaseType::NullPtr || r.base_type() == BaseType::NullPtr {
+      return match op {
+         BinaryOp::Equal(..) if is_null_constant(l) && is_null_constant(r) => Ok(Value::Integer(1, BaseType::Int)),
+         BinaryOp::NotEqual(..) if is_null_constant(l) && is_null_constant(r) => Ok(Value::Integer(0, BaseType::Int)),
+         _ => Err(format!("Invalid operands of types {} and {} to a binary operator", l.base_type(), r.base_type())),
+      };
+   }
+
+   // Shifts promote each operand on its own; the result has the left operand's type.
+   if let BinaryOp::ShiftLeft(..) | BinaryOp::ShiftRight(..) = op {
+      let (Value::Integer(n, _), Value::Integer(count, _)) = (promote_value(l), promote_value(r)) else {
+         return Err(String::from("Operands of a shift must have integer type"));
+      };
+      let t = promote(l.base_type());
+      let (bits, _) = integer_layout(t).unwrap_or((64, true));
+      if count < 0 || count >= bits as i128 {
+         if evaluated {
+            return Err(String::from("Shift count out of range in constant expression"));
+         }
+         return Ok(Value::Integer(0, t));
+      }
+      return match op {
+         BinaryOp::ShiftLeft(..) if is_signed(t) && n < 0 && evaluated => {
+            Err(String::from("Left shift of a negative value in constant expression"))
+         },
+         BinaryOp::ShiftLeft(..) if is_signed(t) => integer_result(n << count, t, evaluated),
+         BinaryOp::ShiftLeft(..) => Ok(Value::Integer(wrap(((n as u128) << count) as i128, t), t)),
+         _ => Ok(Value::Integer(n >> count, t)),
+      };
+   }
+
+   let t = common_type(l.base_type(), r.base_type());
+   let (l, r) = (convert(l, t, evaluated)?, convert(r, t, evaluated)?);
+   match (l, r) {
+      (Value::Integer(n, _), Value::Integer(m, _)) => eval_integer_binary(op, n, m, t, evaluated),
+      (Value::Floating(f, _), Value::Floating(g, _)) => eval_floating_binary(op, f, g, t),
+      _ => Err(String::from("Mismatched operands in constant expression")),
+   }
+}
+
+/// Applies an arithmetic, bitwise or comparison operator to operands already converted to `t`.
+fn eval_integer_binary(op: &BinaryOp, n: i128, m: i128, t: BaseType, evaluated: bool) -> Result<Value, String> {
+   let compare = |result: bool| Ok(Value::Integer(result as i128, BaseType::Int));
+   match op {
+      // Unsigned operands may exceed i128 when multiplied; wrapping keeps the low bits right.
+      BinaryOp::Add(..) => integer_result(n.wrapping_add(m), t, evaluated),
+      BinaryOp::Subtract(..) => integer_result(n.wrapping_sub(m), t, evaluated),
+      BinaryOp::Multiply(..) => integer_result(n.wrapping_mul(m), t, evaluated),
+      BinaryOp::Divide(..) | BinaryOp::Remainder(..) => {
+         if m == 0 {
+            if evaluated {
+               return Err(String::from("Division by zero in constant expression"));
+            }
+            return Ok(Value::Integer(0, t));
+         }
+         // For INT_MIN % -1 the quotient is unrepresentable, which makes the remainder undefined too.
+         let quotient = integer_result(n / m, t, evaluated)?;
+         match op {
+            BinaryOp::Divide(..) => Ok(quotient),
+            _ => Ok(Value::Integer(n % m, t)),
+         }
+      },
+      BinaryOp::BitAnd(..) => Ok(Value::Integer(wrap(n & m, t), t)),
+      BinaryOp::BitOr(..) => Ok(Value::Integer(wrap(n | m, t), t)),
+      BinaryOp::BitXor(..) => Ok(Value::Integer(wrap(n ^ m, t), t)),
+      BinaryOp::LessThan(..) => compare(n < m),
+      BinaryOp::LessEqual(..) => compare(n <= m),
+      BinaryOp::GreaterThan(..) => compare(n > m),
+      BinaryOp::GreaterEqual(..) => compare(n >= m),
+      BinaryOp::Equal(..) => compare(n == m),
+      BinaryOp::NotEqual(..) => compare(n != m),
+      BinaryOp::ShiftLeft(..) | BinaryOp::ShiftRight(..) | BinaryOp::And(..) | BinaryOp::Or(..) => {
+         unreachable!("handled by eval_binary")
+      },
+   }
+}
+
+fn eval_floating_binary(op: &BinaryOp, f: f64, g: f64, t: BaseType) -> Result<Value, String> {
+   let compare = |result: bool| Ok(Value::Integer(result as i128, BaseType::Int));
+   match op {
+      BinaryOp::Add(..) => Ok(floating_result(f + g, t)),
+      BinaryOp::Subtract(..) => Ok(floating_result(f - g, t)),
+      BinaryOp::Multiply(..) => Ok(floating_result(f * g, t)),
+      BinaryOp::Divide(..) => Ok(floating_result(f / g, t)),
+      BinaryOp::LessThan(..) => compare(f < g),
+      BinaryOp::LessEqual(..) => compare(f <= g),
+      BinaryOp::GreaterThan(..) => compare(f > g),
+      BinaryOp::GreaterEqual(..) => compare(f >= g),
+      BinaryOp::Equal(..) => compare(f == g),
+      BinaryOp::NotEqual(..) => compare(f != g),
+      _ => Err(String::from("Operands of a bitwise operator or '%' must have integer type")),
+   }
+}
+
+/// Checks the operand rule for integer constant expressions (C17 6.6p6): floating
+/// constants may only appear as the immediate operand of a cast to an integer type,
+/// and `sizeof` operands are not examined.
+fn check_integer_operands(expr: &Expr) -> Result<(), String> {
+   match &expr.kind {
+      ExprKind::Const(Constant::Floating(..)) => Err(String::from("Floating constant in an integer constant expression")),
+      ExprKind::Const(Constant::Integer(..)) | ExprKind::Nullptr | ExprKind::Sizeof(_) | ExprKind::SizeofType(_) | ExprKind::Alignof(_) => Ok(()),
+      // C23 also allows named constants of integer type (6.6p8).
+      ExprKind::Constexpr(name, value) => match type_of(value)?.unqualified() {
+         Type::Base(b) if is_integer(*b) => Ok(()),
+         t => Err(format!("constexpr object \"{}\" of type {} in an integer constant expression", name, t)),
+      },
+      ExprKind::Generic(control, associations) => check_integer_operands(select_generic(control, associations)?),
+      ExprKind::Paren(e) => check_integer_operands(e),
+      ExprKind::Unary(op) => match &**op {
+         UnaryOp::Complement(e) | UnaryOp::Negate(e) | UnaryOp::Plus(e) | UnaryOp::Not(e) => check_integer_operands(e),
+      },
+      ExprKind::Binary(op) => {
+         let (left, right) = op.operands();
+         check_integer_operands(left)?;
+         check_integer_operands(right)
+      },
+      ExprKind::Conditional(cond, then, otherwise) => {
+         check_integer_operands(cond)?;
+         check_integer_operands(then)?;
+         check_integer_operands(otherwise)
+      },
+      ExprKind::Cast(t, e) => {
+         if !matches!(t.unqualified(), Type::Base(b) if is_integer(*b)) {
+            return Err(format!("Cast to {} in an integer constant expression", t));
+         }
+         let mut operand = &**e;
+         while let ExprKind::Paren(inner) = &operand.kind {
+            operand = inner;
+         }
+         match &operand.kind {
+            ExprKind::Const(Constant::Floating(..)) | ExprKind::Constexpr(..) => Ok(()),
+            _ => check_integer_operands(operand),
+         }
+      },
+   }
+}
+
+/// Evaluates an integer constant expression (C17 6.6p6).
+pub fn integer_constant(expr: &Expr) -> Result<i128, String> {
+   check_integer_operands(expr)?;
+   match eval(expr, true)? {
+      Value::Integer(_, BaseType::NullPtr) => Err(String::from("Integer constant expression has type nullptr_t")),
+      Value::Integer(n, _) => Ok(n),
+      Value::Floating(..) => Err(String::from("Integer constant expression has floating type")),
+   }
+}
+
+/// Evaluates an arithmetic constant expression (C17 6.6p8), as allowed in initializers.
+pub fn arithmetic_constant(expr: &Expr) -> Result<Value, String> {
+   eval(expr, true)
+}
+
+/// Folds an expression outside a constant-expression context, such as a return value.
+/// Nothing requires it to be a constant there, so faults like overflow or division by
+/// zero are left to run time: the value wraps, or is 0 when it is undefined.
+pub fn fold(expr: &Expr) -> Result<Value, String> {
+   eval(expr, false)
+}
+
+/// Checks that a constexpr initializer is a constant whose value `t` represents exactly,
+/// without the rounding or wrapping an ordinary conversion would allow (C23 6.7.1p5).
+pub fn exact_constant(expr: &Expr, t: &Type) -> Result<(), String> {
+   let Type::Base(target) = t.unqualified() else {
+      return Err(format!("constexpr objects of type {} are not supported", t));
+   };
+   let value = eval(expr, true)?;
+   let converted = convert(value, *target, true)?;
+   let is_exact = match (value, converted) {
+      (Value::Integer(n, _), Value::Integer(m, _)) => n == m,
+      (Value::Integer(n, _), Value::Floating(f, _)) => f == n as f64 && f as i128 == n,
+      (Value::Floating(f, _), Value::Integer(m, _)) => f == m as f64,
+      (Value::Floating(f, _), Value::Floating(g, _)) => f == g || (f.is_nan() && g.is_nan()),
+   };
+   if !is_exact {
+      return Err(format!("Value of the initializer is not exactly representable in {}", t));
+   }
+   Ok(())
+}
+
+/// The type of an expression, which is not evaluated.
+pub fn type_of(expr: &Expr) -> Result<Type, String> {
+   match &expr.kind {
+      ExprKind::Paren(e) => type_of(e),
+      // A constexpr object is an lvalue, so it keeps the qualifiers of its declared type.
+      ExprKind::Constexpr(_, value) => match &value.kind {
+         ExprKind::Cast(t, _) => Ok((**t).clone()),
+         _ => type_of(value),
+      },
+      _ => Ok(Type::Base(eval(expr, false)?.base_type())),
+   }
+}
+
+/// The number of elements given by an array declarator's size expression.
+pub fn array_length(size: &Expr) -> Result<u64, String> {
+   let n = integer_constant(size)?;
+   if n <= 0 {
+      return Err(String::from("Size of array is not positive"));
+   }
+   Ok(n as u64)
+}
+
+/// Alignment in bytes of a complete object type on x86-64.
+pub fn align_of(t: &Type) -> Result<u64, String> {
+   match t {
+      Type::Qualified(_, t) => align_of(t),
+      Type::Base(BaseType::Void) => Err(String::from("Invalid application of _Alignof to void")),
+      Type::Base(_) | Type::Pointer(_) => size_of(t),
+      Type::Array(element, Some(_)) => align_of(element),
+      Type::Array(_, None) => Err(String::from("Invalid application of _Alignof to an incomplete array type")),
+      Type::Function(..) => Err(String::from("Invalid application of _Alignof to a function type")),
+   }
+}
+
+/// Size in bytes of a complete object type on x86-64.
+pub fn size_of(t: &Type) -> Result<u64, String> {
+   match t {
+      Type::Qualified(_, t) => size_of(t),
+      Type::Base(b) => match b {
+         BaseType::Void => Err(String::from("Invalid application of sizeof to void")),
+         BaseType::Bool | BaseType::Char | BaseType::SChar | BaseType::UChar => Ok(1),
+         BaseType::Short | BaseType::UShort => Ok(2),
+         BaseType::Int | BaseType::UInt | BaseType::Float => Ok(4),
+         BaseType::LongDouble => Ok(16),
+         _ => Ok(8),
+      },
+      Type::Pointer(_) => Ok(8),
+      Type::Array(element, Some(size)) => {
+         array_length(size)?.checked_mul(size_of(element)?)
+            .ok_or_else(|| format!("Type {} is too large", t))
+      },
+      Type::Array(_, None) => Err(String::from("Invalid application of sizeof to an incomplete array type")),
+      Type::Function(..) => Err(String::from("Invalid application of sizeof to a function type")),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::cli::Standard;
+   use crate::error::ErrorCode;
+   use crate::lexer::Lexer;
+   use crate::parser::ast::{ExternalDecl, Initializer, Program};
+
+   /// The initializer of the last declaration in `source`.
+   fn initializer(source: &str) -> Expr {
+      let tokens = Lexer::new(source.to_string(), "test.c", Standard::C23).lex().expect("lexes");
+      let Program::Program(decls) = Program::parse(&tokens, Standard::C23).expect("parses");
+      match decls.last() {
+         Some(ExternalDecl::Declaration(d)) => match &d.declarators[0].init {
+            Some(Initializer::Single(e)) => e.clone(),
+            _ => panic!("no initializer in {}", source),
+         },
+         _ => panic!("no declaration in {}", source),
+      }
+   }
+
+   fn expr(text: &str) -> Expr {
+      initializer(&format!("int x = {};", text))
+   }
+
+   fn value(text: &str) -> Value {
+      arithmetic_constant(&expr(text)).unwrap_or_else(|e| panic!("{}: {}", text, e))
+   }
+
+   /// The value and type of an arithmetic constant expression of integer type.
+   fn integer(text: &str) -> (i128, BaseType) {
+      match value(text) {
+         Value::Integer(n, t) => (n, t),
+         other => panic!("{}: expected an integer, got {:?}", text, other),
+      }
+   }
+
+   fn floating(text: &str) -> (f64, BaseType) {
+      match value(text) {
+         Value::Floating(f, t) => (f, t),
+         other => panic!("{}: expected a floating value, got {:?}", text, other),
+      }
+   }
+
+   fn rejects(text: &str) {
+      assert!(arithmetic_constant(&expr(text)).is_err(), "{} should be rejected", text);
+   }
+
+   #[test]
+   fn signed_overflow() {
+      rejects("2147483647 + 1");
+      rejects("-2147483647 - 2");
+      rejects("-(-2147483647 - 1)");
+      rejects("9223372036854775807L * 2");
+      assert_eq!(integer("-2147483647 - 1"), (-2147483648, BaseType::Int));
+      assert_eq!(integer("4294967295u + 1"), (0, BaseType::UInt));
+      assert_eq!(integer("0ul - 1"), (u64::MAX as i128, BaseType::ULong));
+      assert_eq!(integer("18446744073709551615u * 18446744073709551615u"), (1, BaseType::ULong));
+   }
+
+   #[test]
+   fn division_by_zero() {
+      rejects("1 / 0");
+      rejects("1 % 0");
+      rejects("(-2147483647 - 1) / -1");
+      rejects("(-2147483647 - 1) % -1");
+      assert_eq!(integer("-7 / 2"), (-3, BaseType::Int));
+      assert_eq!(integer("-7 % 2"), (-1, BaseType::Int));
+      assert_eq!(floating("1.0 / 0"), (f64::INFINITY, BaseType::Double));
+   }
+
+   #[test]
+   fn unevaluated_operands_do_not_fault() {
+      assert_eq!(integer("0 ? 1 / 0 : 2").0, 2);
+      assert_eq!(integer("1 || 1 / 0").0, 1);
+      assert_eq!(integer("0 && 1 << 99").0, 0);
+      assert_eq!(integer("sizeof(1 / 0)").0, 4);
+      assert_eq!(integer("_Generic(1, long: 1 / 0, int: 3)").0, 3);
+   }
+
+   #[test]
+   fn shift_range() {
+      rejects("1 << 32");
+      rejects("1 << -1");
+      rejects("1 >> 32");
+      rejects("1L << 64");
+      rejects("-1 << 1");
+      rejects("1 << 31");
+      assert_eq!(integer("1 << 30"), (1 << 30, BaseType::Int));
+      assert_eq!(integer("1u << 31"), (1 << 31, BaseType::UInt));
+      assert_eq!(integer("1L << 32"), (1 << 32, BaseType::Long));
+      assert_eq!(integer("-8 >> 1"), (-4, BaseType::Int));
+      // The result has the promoted type of the left operand, whatever the right one is.
+      assert_eq!(integer("(char)1 << 1L"), (2, BaseType::Int));
+   }
+
+   #[test]
+   fn usual_arithmetic_conversions() {
+      assert_eq!(integer("-1 < 0u").0, 0);
+      assert_eq!(integer("-1 < 0L").0, 1);
+      assert_eq!(integer("-1L < 0u").0, 1);
+      assert_eq!(integer("-1L < 0ul").0, 0);
+      assert_eq!(integer("-1 + 0u"), (u32::MAX as i128, BaseType::UInt));
+      assert_eq!(integer("1L + 1u").1, BaseType::Long);
+      assert_eq!(integer("1LL + 1ul").1, BaseType::ULongLong);
+      assert_eq!(integer("(char)1 + (char)1").1, BaseType::Int);
+      assert_eq!(integer("(unsigned short)1 + 1u").1, BaseType::UInt);
+      assert_eq!(integer("'a' + 'b'"), (195, BaseType::Int));
+      assert_eq!(integer("1 == 1.0"), (1, BaseType::Int));
+      assert_eq!(floating("1.0f + 1"), (2.0, BaseType::Float));
+      assert_eq!(floating("1.0f + 1.0"), (2.0, BaseType::Double));
+      assert_eq!(floating("1 ? 1 : 1.0f"), (1.0, BaseType::Float));
+   }
+
+   #[test]
+   fn integer_constant_expressions() {
+      let integer_constant = |text: &str| integer_constant(&expr(text));
+      assert_eq!(integer_constant("(int)1.5"), Ok(1));
+      assert_eq!(integer_constant("sizeof(double)"), Ok(8));
+      assert!(integer_constant("1.5 > 1").is_err());
+      assert!(integer_constant("(int)(1.5 + 1)").is_err());
+      assert!(integer_constant("nullptr").is_err());
+   }
+
+   #[test]
+   fn generic_selection() {
+      assert_eq!(integer("_Generic(1L, int: 1, long: 2, default: 3)").0, 2);
+      assert_eq!(integer("_Generic(1.0f, default: 3, float: 4)").0, 4);
+      assert_eq!(integer("_Generic('a', char: 1, int: 2)").0, 2);
+      assert_eq!(integer("_Generic(1u, int: 1, default: 3)").0, 3);
+      assert_eq!(integer("_Generic((const int)1, int: 1, default: 2)").0, 1);
+      rejects("_Generic(1, long: 1)");
+      rejects("_Generic(1, int: 1, signed: 2)");
+      rejects("_Generic(1, default: 1, default: 2)");
+      rejects("_Generic(1, void: 1, default: 2)");
+   }
+
+   #[test]
+   fn integer_conversions_wrap() {
+      assert_eq!(integer("(unsigned char)-1"), (255, BaseType::UChar));
+      assert_eq!(integer("(signed char)200"), (-56, BaseType::SChar));
+      assert_eq!(integer("(unsigned)-1L"), (u32::MAX as i128, BaseType::UInt));
+      assert_eq!(integer("(_Bool)256"), (1, BaseType::Bool));
+      assert_eq!(integer("(_Bool)0.5"), (1, BaseType::Bool));
+   }
+
+   #[test]
+   fn floating_conversion_boundaries() {
+      rejects("(long)9223372036854775808.0");
+      assert_eq!(integer("(long)9223372036854774784.0"), (9223372036854774784, BaseType::Long));
+      assert_eq!(integer("(long)-9223372036854775808.0"), (i64::MIN as i128, BaseType::Long));
+      rejects("(unsigned long)18446744073709551616.0");
+      assert_eq!(integer("(unsigned long)18446744073709549568.0"), (18446744073709549568, BaseType::ULong));
+      rejects("(int)2147483648.0");
+      assert_eq!(integer("(int)2147483647.9"), (i32::MAX as i128, BaseType::Int));
+      assert_eq!(integer("(int)-2147483648.9"), (i32::MIN as i128, BaseType::Int));
+      rejects("(int)-2147483649.0");
+      assert_eq!(integer("(unsigned)-0.9"), (0, BaseType::UInt));
+      rejects("(unsigned)-1.0");
+      assert_eq!(integer("(unsigned char)255.9"), (255, BaseType::UChar));
+      rejects("(unsigned char)256.0");
+      rejects("(int)(0.0 / 0)");
+      rejects("(int)(1.0 / 0)");
+   }
+
+   #[test]
+   fn folding_outside_constant_expressions_does_not_fault() {
+      let fold = |text: &str| match fold(&expr(text)) {
+         Ok(Value::Integer(n, t)) => (n, t),
+         other => panic!("{}: expected an integer, got {:?}", text, other),
+      };
+      assert_eq!(fold("(int)1e10"), (0, BaseType::Int));
+      assert_eq!(fold("1 << 32").1, BaseType::Int);
+      assert_eq!(fold("1 / 0"), (0, BaseType::Int));
+      assert_eq!(fold("2147483647 + 1"), (i32::MIN as i128, BaseType::Int));
+   }
+
+   #[test]
+   fn constexpr_initializers_must_be_exact() {
+      let exact = |text: &str, t: BaseType| exact_constant(&expr(text), &Type::Base(t));
+      assert!(exact("255", BaseType::UChar).is_ok());
+      assert!(exact("256", BaseType::UChar).is_err());
+      assert!(exact("-1", BaseType::UInt).is_err());
+      assert!(exact("2.0", BaseType::Int).is_ok());
+      assert!(exact("2.5", BaseType::Int).is_err());
+      assert!(exact("16777217", BaseType::Float).is_err());
+      assert!(exact("0.1", BaseType::Float).is_err());
+      assert!(exact("0.5", BaseType::Float).is_ok());
+      assert!(exact("nullptr", BaseType::NullPtr).is_ok());
+      assert!(exact("0", BaseType::NullPtr).is_err());
+   }
+
+   #[test]
+   fn unary_plus_promotes_its_operand() {
+      assert_eq!(integer("+1"), (1, BaseType::Int));
+      assert_eq!(integer("+(char)1"), (1, BaseType::Int));
+      assert_eq!(integer("1 + +2"), (3, BaseType::Int));
+      assert_eq!(integer("1 - -2"), (3, BaseType::Int));
+      assert_eq!(floating("+1.5f"), (1.5, BaseType::Float));
+      // `++` and `--` are single tokens, not two unary operators.
+      for text in ["++1", "1 +++ 2", "--1", "1 ++", "1 += 2"] {
+         let tokens = Lexer::new(format!("int x = {};", text), "test.c", Standard::C23).lex().expect("lexes");
+         let Err(error) = Program::parse(&tokens, Standard::C23) else {
+            panic!("{} should be rejected", text);
+         };
+         assert_eq!(error.diagnostic().code, ErrorCode::Unsupported, "{}", text);
+      }
+   }
+}
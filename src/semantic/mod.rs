@@ -0,0 +1,400 @@
+mod compat;
+pub mod const_eval;
+
+use std::collections::HashMap;
+use crate::cli::Standard;
+use crate::error::{CompileError, ErrorCode};
+use crate::parser::ast;
+use crate::parser::types::{AlignmentSpecifier, BaseType, Params, StorageClass, Type};
+use crate::span::Span;
+use compat::{compatible, composite};
+use const_eval::{align_of, arithmetic_constant, array_length, exact_constant, fold, integer_constant};
+
+#[derive(Clone, Copy, PartialEq)]
+enum SymbolKind {
+   Function,
+   Object,
+   Typedef,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Linkage {
+   External,
+   Internal,
+   None,
+}
+
+/// An identifier as declared in one particular scope.
+struct ScopeEntry {
+   kind: SymbolKind,
+   linkage: Linkage,
+   decl_type: Type,
+   span: Span,
+}
+
+/// A function or object with linkage. Every declaration of it, in any scope, must agree.
+struct LinkedSymbol {
+   kind: SymbolKind,
+   decl_type: Type,
+   /// The most recent declaration.
+   declaration: Span,
+   definition: Option<Span>,
+}
+
+/// Checks that the declarations in a translation unit are consistent with each other:
+/// compatible redeclarations, consistent linkage and at most one definition. Errors are
+/// located at the declarator or expression at fault.
+pub struct Validator<'a> {
+   program: &'a ast::Program,
+   std: Standard,
+   scopes: Vec<HashMap<String, ScopeEntry>>,
+   linked: HashMap<String, LinkedSymbol>,
+}
+
+impl<'a> Validator<'a> {
+   pub fn new(program_: &'a ast::Program, std_: Standard) -> Self {
+      Validator {
+         program: program_,
+         std: std_,
+         scopes: vec![HashMap::new()],
+         linked: HashMap::new(),
+      }
+   }
+
+   pub fn validate(&mut self) -> Result<(), CompileError> {
+      let program = self.program;
+      match program {
+         ast::Program::Program(decls) => {
+            for decl in decls {
+               match decl {
+                  ast::ExternalDecl::Function(f) => self.validate_function(f)?,
+                  ast::ExternalDecl::Declaration(d) => self.validate_declaration(d)?,
+                  ast::ExternalDecl::StaticAssert(s) => Self::validate_static_assert(s)?,
+               }
+            }
+         }
+      }
+      Ok(())
+   }
+
+   fn validate_function(&mut self, function: &ast::Function) -> Result<(), CompileError> {
+      Self::validate_array_sizes(&function.name, &function.func_type)?;
+      self.declare(&function.name, SymbolKind::Function, &function.func_type, function.specifiers.storage_class, true, &function.span)?;
+
+      self.scopes.push(HashMap::new());
+      let result = self.validate_body(function);
+      self.scopes.pop();
+      result
+   }
+
+   /// Parameters and the outermost block of the body share one scope.
+   fn validate_body(&mut self, function: &ast::Function) -> Result<(), CompileError> {
+      let params = match &function.func_type {
+         Type::Function(_, Params::Prototype(params, _)) | Type::Function(_, Params::OldStyle(params)) => params.as_slice(),
+         _ => &[],
+      };
+      for param in params {
+         let name = param.name.as_deref().unwrap_or_default();
+         if let Some(prior) = self.current_scope().get(name) {
+            let error = CompileError::semantic(ErrorCode::Redefinition, format!("Redefinition of parameter \"{}\"", name));
+            return Err(error.at(&param.span).with_note(format!("previous declaration of \"{}\" was here", name), Some(prior.span.clone())));
+         }
+         Self::validate_array_sizes(name, &param.declared_type)?;
+         self.declare(name, SymbolKind::Object, &param.param_type, None, false, &param.span)?;
+      }
+
+      for item in &function.body {
+         let result = match item {
+            ast::BlockItem::Declaration(d) => self.validate_declaration(d),
+            ast::BlockItem::StaticAssert(s) => Self::validate_static_assert(s),
+            ast::BlockItem::Stmt(ast::Stmt { kind: ast::StmtKind::Return(e), .. }) => Self::validate_return(function, e),
+         };
+         result.map_err(|e| e.at(item.span()))?;
+      }
+      Ok(())
+   }
+
+   /// Checks the operands of a returned expression and its conversion to the return
+   /// type. nullptr_t converts only to itself and bool (C23 6.3.2.4).
+   fn validate_return(function: &ast::Function, expr: &ast::Expr) -> Result<(), CompileError> {
+      let error = |message| CompileError::semantic(ErrorCode::InvalidOperands, message).at(&expr.span);
+      let from = fold(expr).map_err(error)?.base_type();
+      if let Type::Function(ret, _) = function.func_type.unqualified()
+         && let Type::Base(to) = ret.unqualified()
+         && (from == BaseType::NullPtr) != (*to == BaseType::NullPtr) && *to != BaseType::Bool {
+         return Err(error(format!("Cannot convert {} to {} in return", from, ret)));
+      }
+      Ok(())
+   }
+
+   fn validate_declaration(&mut self, declaration: &ast::Declaration) -> Result<(), CompileError> {
+      let storage_class = declaration.specifiers.storage_class;
+      for declarator in &declaration.declarators {
+         let kind = if storage_class == Some(StorageClass::Typedef) {
+            SymbolKind::Typedef
+         } else if declarator.decl_type.is_function() {
+            SymbolKind::Function
+         } else {
+            SymbolKind::Object
+         };
+         let at_file_scope = self.scopes.len() == 1;
+         if !at_file_scope && storage_class == Some(StorageClass::Extern) && declarator.init.is_some() {
+            let message = format!("Block-scope extern declaration of \"{}\" cannot have an initializer", declarator.name);
+            return Err(CompileError::semantic(ErrorCode::InvalidInitializer, message).at(&declarator.span));
+         }
+         Self::validate_array_sizes(&declarator.name, &declarator.decl_type)?;
+         Self::validate_alignment(declarator, &declaration.specifiers.alignment)?;
+         // Objects with static storage duration are initialized before the program starts.
+         let is_static = at_file_scope || storage_class == Some(StorageClass::Static);
+         if let Some(init) = &declarator.init && is_static {
+            Self::validate_constant_initializer(init)?;
+         }
+         if declaration.specifiers.is_constexpr && let Some(value) = declarator.init.as_ref().and_then(ast::Initializer::scalar_value) {
+            exact_constant(&value, &declarator.decl_type)
+               .map_err(|e| {
+                  let message = format!("Invalid initializer for constexpr object \"{}\": {}", declarator.name, e);
+                  CompileError::semantic(ErrorCode::InvalidInitializer, message).at(&value.span)
+                     .with_label(&declarator.span, format!("declared here with type {}", declarator.decl_type))
+               })?;
+         }
+         self.declare(&declarator.name, kind, &declarator.decl_type, storage_class, declarator.init.is_some(), &declarator.span)?;
+      }
+      Ok(())
+   }
+
+   fn validate_static_assert(static_assert: &ast::StaticAssert) -> Result<(), CompileError> {
+      let condition = &static_assert.condition;
+      let value = integer_constant(condition)
+         .map_err(|e| CompileError::semantic(ErrorCode::NotConstant, e).at(&condition.span))?;
+      if value == 0 {
+         let message = match &static_assert.message {
+            Some(message) => format!("Static assertion failed: \"{}\"", message),
+            None => String::from("Static assertion failed"),
+         };
+         let error = CompileError::semantic(ErrorCode::StaticAssertionFailed, message).at(&static_assert.span);
+         return Err(error.with_label(&condition.span, "this condition is false"));
+      }
+      Ok(())
+   }
+
+   /// Every array bound in a declared type, including those in parameter and return
+   /// types, must be a positive integer constant expression.
+   fn validate_array_sizes(name: &str, decl_type: &Type) -> Result<(), CompileError> {
+      match decl_type {
+         Type::Base(_) => Ok(()),
+         Type::Qualified(_, t) | Type::Pointer(t) => Self::validate_array_sizes(name, t),
+         Type::Array(element, size) => {
+            if let Some(size) = size {
+               array_length(size).map_err(|e| {
+                  let message = format!("Invalid size for array \"{}\": {}", name, e);
+                  CompileError::semantic(ErrorCode::InvalidArraySize, message).at(&size.span)
+               })?;
+            }
+            Self::validate_array_sizes(name, element)
+         },
+         Type::Function(ret, params) => {
+            if let Params::Prototype(params, _) | Params::OldStyle(params) = params {
+               for param in params {
+                  Self::validate_array_sizes(param.name.as_deref().unwrap_or(name), &param.declared_type)?;
+               }
+            }
+            Self::validate_array_sizes(name, ret)
+         },
+      }
+   }
+
+   /// An `_Alignas` request must be a power of two, or zero for no effect, and may not
+   /// weaken the natural alignment of the type; the strictest request wins (C17 6.7.5).
+   fn validate_alignment(declarator: &ast::InitDeclarator, alignment: &[AlignmentSpecifier]) -> Result<(), CompileError> {
+      let name = &declarator.name;
+      let error = |message| CompileError::semantic(ErrorCode::InvalidAlignment, message).at(&declarator.span);
+      let mut strictest = 0;
+      for specifier in alignment {
+         let requested = match specifier {
+            AlignmentSpecifier::Type(t) => align_of(t).map_err(error)?,
+            AlignmentSpecifier::Expr(e) => {
+               let n = integer_constant(e)
+                  .map_err(|message| CompileError::semantic(ErrorCode::NotConstant, message).at(&e.span))?;
+               if n < 0 || (n != 0 && !(n as u64).is_power_of_two()) {
+                  let message = format!("Requested alignment {} for \"{}\" is not a power of two", n, name);
+                  return Err(CompileError::semantic(ErrorCode::InvalidAlignment, message).at(&e.span));
+               }
+               n as u64
+            },
+         };
+         strictest = strictest.max(requested);
+      }
+      let natural = if strictest == 0 { 0 } else { align_of(&declarator.decl_type).map_err(error)? };
+      if strictest < natural {
+         return Err(error(format!("_Alignas({}) would weaken the {}-byte alignment of \"{}\"", strictest, natural, name)));
+      }
+      Ok(())
+   }
+
+   fn validate_constant_initializer(init: &ast::Initializer) -> Result<(), CompileError> {
+      match init {
+         ast::Initializer::Single(expr) => arithmetic_constant(expr).map(|_| ())
+            .map_err(|e| CompileError::semantic(ErrorCode::NotConstant, e).at(&expr.span)),
+         ast::Initializer::List(inits, _) => inits.iter().try_for_each(Self::validate_constant_initializer),
+      }
+   }
+
+   fn current_scope(&self) -> &HashMap<String, ScopeEntry> {
+      self.scopes.last().expect("there is always a file scope")
+   }
+
+   /// Records a declaration in the innermost scope after checking it against earlier
+   /// declarations of the same identifier (C17 6.2.2 and 6.7p3-4). Conflicts are reported
+   /// at `span`, with a note pointing at the earlier declaration.
+   fn declare(&mut self, name: &str, kind: SymbolKind, decl_type: &Type, storage_class: Option<StorageClass>, is_definition: bool, span: &Span) -> Result<(), CompileError> {
+      let at_file_scope = self.scopes.len() == 1;
+      let visible_linkage = self.scopes.iter().rev()
+         .find_map(|scope| scope.get(name))
+         .map(|entry| entry.linkage)
+         .filter(|linkage| *linkage != Linkage::None);
+      let linkage = match kind {
+         SymbolKind::Typedef => Linkage::None,
+         _ if at_file_scope && storage_class == Some(StorageClass::Static) => Linkage::Internal,
+         SymbolKind::Function => visible_linkage.unwrap_or(Linkage::External),
+         SymbolKind::Object if at_file_scope && storage_class.is_none() => Linkage::External,
+         SymbolKind::Object if storage_class == Some(StorageClass::Extern) => visible_linkage.unwrap_or(Linkage::External),
+         SymbolKind::Object => Linkage::None,
+      };
+
+      let error = |code, message| CompileError::semantic(code, message).at(span);
+      let previous = |what, prior: &Span| (format!("previous {} of \"{}\" was here", what, name), Some(prior.clone()));
+
+      if let Some(prior) = self.current_scope().get(name) {
+         let (note, note_span) = previous("declaration", &prior.span);
+         if prior.kind != kind {
+            return Err(error(ErrorCode::Redeclaration, format!("\"{}\" redeclared as a different kind of symbol", name)).with_note(note, note_span));
+         }
+         if kind == SymbolKind::Typedef {
+            if self.std < Standard::C11 {
+               return Err(error(ErrorCode::Redefinition, format!("Redefinition of typedef \"{}\"", name)).with_note(note, note_span));
+            }
+            if !compatible(&prior.decl_type, decl_type) {
+               let message = format!("Conflicting types for typedef \"{}\" ({} and {})", name, prior.decl_type, decl_type);
+               return Err(error(ErrorCode::ConflictingTypes, message).with_note(note, note_span));
+            }
+         } else if prior.linkage == Linkage::External && linkage == Linkage::Internal {
+            let message = format!("Static declaration of \"{}\" follows non-static declaration", name);
+            return Err(error(ErrorCode::Redeclaration, message).with_note(note, note_span));
+         } else if prior.linkage == Linkage::Internal && linkage == Linkage::External {
+            let message = format!("Non-static declaration of \"{}\" follows static declaration", name);
+            return Err(error(ErrorCode::Redeclaration, message).with_note(note, note_span));
+         } else if prior.linkage == Linkage::None || linkage == Linkage::None {
+            return Err(error(ErrorCode::Redeclaration, format!("Redeclaration of \"{}\" with no linkage", name)).with_note(note, note_span));
+         }
+      }
+
+      let mut entry_type = decl_type.clone();
+      if linkage != Linkage::None {
+         match self.linked.get_mut(name) {
+            Some(symbol) => {
+               let (note, note_span) = previous("declaration", &symbol.declaration);
+               if symbol.kind != kind {
+                  return Err(error(ErrorCode::Redeclaration, format!("\"{}\" redeclared as a different kind of symbol", name)).with_note(note, note_span));
+               }
+               if !compatible(&symbol.decl_type, decl_type) {
+                  let message = format!("Conflicting types for \"{}\" ({} and {})", name, symbol.decl_type, decl_type);
+                  return Err(error(ErrorCode::ConflictingTypes, message).with_note(note, note_span));
+               }
+               if is_definition && let Some(definition) = &symbol.definition {
+                  let message = match kind {
+                     SymbolKind::Function => format!("Redefinition of function \"{}\"", name),
+                     _ => format!("Redefinition of \"{}\"", name),
+                  };
+                  let (note, note_span) = previous("definition", definition);
+                  return Err(error(ErrorCode::Redefinition, message).with_note(note, note_span));
+               }
+               symbol.decl_type = composite(&symbol.decl_type, decl_type);
+               symbol.declaration = span.clone();
+               if is_definition {
+                  symbol.definition = Some(span.clone());
+               }
+               entry_type = symbol.decl_type.clone();
+            },
+            None => {
+               let definition = if is_definition { Some(span.clone()) } else { None };
+               self.linked.insert(name.to_string(), LinkedSymbol { kind, decl_type: decl_type.clone(), declaration: span.clone(), definition });
+            }
+         }
+      }
+
+      if let Some(scope) = self.scopes.last_mut() {
+         scope.insert(name.to_string(), ScopeEntry { kind, linkage, decl_type: entry_type, span: span.clone() });
+      }
+      Ok(())
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::lexer::Lexer;
+   use crate::parser::Parser;
+
+   fn validate(source: &str, std: Standard) -> Result<(), CompileError> {
+      let tokens = Lexer::new(source.to_string(), "test.c", std).lex().expect("lexes");
+      let program = Parser::new(tokens, std).parse().expect("parses");
+      Validator::new(&program, std).validate()
+   }
+
+   /// The code of the error that validating `source` as C17 fails with.
+   fn error_code(source: &str) -> ErrorCode {
+      match validate(source, Standard::C17) {
+         Ok(()) => panic!("{} should be rejected", source),
+         Err(error) => error.diagnostic().code,
+      }
+   }
+
+   #[test]
+   fn parameter_array_bounds_are_checked_before_adjustment() {
+      assert_eq!(error_code("int f(int a[-1]) { return 0; }"), ErrorCode::InvalidArraySize);
+      assert_eq!(error_code("int f(int a[1.5]);"), ErrorCode::InvalidArraySize);
+      assert_eq!(error_code("int f(x) int x[-3]; { return 0; }"), ErrorCode::InvalidArraySize);
+      assert_eq!(error_code("int f(int (*g)(int b[0]));"), ErrorCode::InvalidArraySize);
+      assert!(validate("int f(int a[3], int b[]) { return 0; }", Standard::C17).is_ok());
+      assert!(validate("int f(x) int x[3]; { return 0; }", Standard::C17).is_ok());
+   }
+
+   #[test]
+   fn redeclarations_must_agree_on_linkage() {
+      assert_eq!(error_code("extern int x; static int x;"), ErrorCode::Redeclaration);
+      assert_eq!(error_code("int x; static int x;"), ErrorCode::Redeclaration);
+      assert_eq!(error_code("static int x; int x;"), ErrorCode::Redeclaration);
+      assert_eq!(error_code("int f(void); static int f(void);"), ErrorCode::Redeclaration);
+      assert_eq!(error_code("int f(void) { int x; int x; return 0; }"), ErrorCode::Redeclaration);
+      assert_eq!(error_code("int x; int x(void);"), ErrorCode::Redeclaration);
+      // `extern` and a function without storage class take the linkage already visible.
+      assert!(validate("static int x; extern int x;", Standard::C17).is_ok());
+      assert!(validate("static int f(void); int f(void);", Standard::C17).is_ok());
+      assert!(validate("static int f(void); int f(void) { return 0; }", Standard::C17).is_ok());
+      assert!(validate("int x; extern int x; int x;", Standard::C17).is_ok());
+   }
+
+   #[test]
+   fn redeclarations_must_have_compatible_types() {
+      assert_eq!(error_code("int f(int); int f(long);"), ErrorCode::ConflictingTypes);
+      assert_eq!(error_code("int f(); int f(char c) { return 0; }"), ErrorCode::ConflictingTypes);
+      assert_eq!(error_code("int f(char); int f(c) char c; { return 0; }"), ErrorCode::ConflictingTypes);
+      assert_eq!(error_code("int f(void); int f(void) { return 0; } int f(void) { return 0; }"), ErrorCode::Redefinition);
+      assert!(validate("int f(); int f(int x) { return 0; }", Standard::C17).is_ok());
+      assert!(validate("int f(int); int f(c) char c; { return 0; }", Standard::C17).is_ok());
+      // A typedef may only be repeated, with the same type, from C11 on.
+      assert_eq!(error_code("typedef int T; typedef long T;"), ErrorCode::ConflictingTypes);
+      assert!(validate("typedef int T; typedef int T;", Standard::C17).is_ok());
+      assert_eq!(validate("typedef int T; typedef int T;", Standard::C99).map_err(|e| e.diagnostic().code), Err(ErrorCode::Redefinition));
+   }
+
+   #[test]
+   fn declarations_accumulate_a_composite_type() {
+      assert!(validate("extern int a[]; extern int a[3];", Standard::C17).is_ok());
+      // The second declaration completes the array, so the third conflicts with it
+      // even though it would match the first on its own.
+      assert_eq!(error_code("extern int a[]; extern int a[3]; extern int a[4];"), ErrorCode::ConflictingTypes);
+      assert_eq!(error_code("int f(); int f(int); int f(long);"), ErrorCode::ConflictingTypes);
+      assert_eq!(error_code("int f(int (*)[]); int f(int (*)[2]); int f(int (*)[3]);"), ErrorCode::ConflictingTypes);
+   }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// A stretch of source text: the file and the 1-based lines and columns where it starts
+/// and ends, and its byte range in the text that was lexed. A column is left out when it
+/// cannot be traced back to the original source, as after macro expansion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+   pub file: Rc<str>,
+   pub line: usize,
+   pub column: Option<usize>,
+   pub end_line: usize,
+   /// The column just past the last character.
+   pub end_column: Option<usize>,
+   pub start: usize,
+   pub end: usize,
+}
+
+impl Span {
+   /// The span from the start of `self` to the end of `other`.
+   pub fn to(&self, other: &Span) -> Span {
+      if other.end <= self.end {
+         return self.clone();
+      }
+      // A span that ends in another file, through an #include, has no end we can show.
+      let (end_line, end_column) = match other.file == self.file {
+         true => (other.end_line, other.end_column),
+         false => (self.line, None),
+      };
+      Span {
+         end_line,
+         end_column,
+         end: other.end,
+         ..self.clone()
+      }
+   }
+}
+
+impl fmt::Display for Span {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self.column {
+         Some(column) => write!(f, "{}:{}:{}", self.file, self.line, column),
+         None => write!(f, "{}:{}", self.file, self.line),
+      }
+   }
+}
@@ -1,5 +1,6 @@
 pub mod tacky_ast;
 
+use crate::error::CompileError;
 use crate::parser::ast;
 
 pub struct Tacky {
@@ -13,8 +14,8 @@ impl Tacky {
       }
    }
 
-   pub fn generate(&mut self) -> Result<tacky_ast::Program, String> {
-      let tacky_ast = tacky_ast::Program::parse(&self.ast)?;
-      Ok(tacky_ast)
+   /// Lowers the AST to TACKY.
+   pub fn generate(&mut self) -> Result<tacky_ast::Program, CompileError> {
+      tacky_ast::Program::parse(&self.ast)
    }
 }
\ No newline at end of file
@@ -1,31 +1,91 @@
+use std::collections::HashSet;
+use crate::error::{CompileError, ErrorCode};
+use crate::lexer::token::Constant;
 use crate::parser::ast;
+use crate::parser::types::{AlignmentSpecifier, BaseType, StorageClass, Type};
+use crate::semantic::const_eval::{self, Value};
 
 pub enum Program {
-   Program(FuncDef),
+   Program(Vec<FuncDef>),
 }
 
 impl Program {
    pub fn pretty_print(&self) {
       println!("Program(");
       match self {
-         Self::Program(f) => {
-            f.pretty_print(1);
+         Self::Program(funcs) => {
+            for f in funcs {
+               f.pretty_print(1);
+            }
          }
       }
       println!(")");
    }
 
-   pub fn parse(ast: &ast::Program) -> Result<Program, String> {
-      let func_def: FuncDef;
+   pub fn parse(ast: &ast::Program) -> Result<Program, CompileError> {
+      let mut func_defs: Vec<FuncDef> = Vec::new();
       match ast {
-         ast::Program::Program(f) => {
-            func_def = FuncDef::parse(f)?;
+         ast::Program::Program(decls) => {
+            let internal = internal_names(decls);
+            for decl in decls {
+               match decl {
+                  ast::ExternalDecl::Function(f) => func_defs.push(FuncDef::parse(f, !internal.contains(f.name.as_str()))?),
+                  ast::ExternalDecl::Declaration(d) => check_declaration(d)?,
+                  ast::ExternalDecl::StaticAssert(_) => (),
+               }
+            }
          }
       }
-      Ok(Program::Program(func_def))
+      Ok(Program::Program(func_defs))
    }
 }
 
+/// Names given internal linkage by a `static` declaration anywhere at file scope. Later
+/// declarations without a storage class keep that linkage.
+fn internal_names(decls: &[ast::ExternalDecl]) -> HashSet<&str> {
+   let mut names = HashSet::new();
+   for decl in decls {
+      match decl {
+         ast::ExternalDecl::Function(f) if f.specifiers.storage_class == Some(StorageClass::Static) => {
+            names.insert(f.name.as_str());
+         },
+         ast::ExternalDecl::Declaration(d) if d.specifiers.storage_class == Some(StorageClass::Static) => {
+            names.extend(d.declarators.iter().map(|d| d.name.as_str()));
+         },
+         _ => (),
+      }
+   }
+   names
+}
+
+/// Declarations of typedefs and functions need no code, and neither do constexpr objects,
+/// whose uses are folded to their values. Anything else that declares an object is
+/// rejected until variables are supported, as is any object `_Alignas` asks to align
+/// more strictly than its type, since nothing would honor the request.
+fn check_declaration(declaration: &ast::Declaration) -> Result<(), CompileError> {
+   let specifiers = &declaration.specifiers;
+   if specifiers.storage_class == Some(StorageClass::Typedef) {
+      return Ok(());
+   }
+   // The validator has checked the specifiers, so none of them fails to evaluate.
+   let requested = specifiers.alignment.iter().filter_map(|specifier| match specifier {
+      AlignmentSpecifier::Type(t) => const_eval::align_of(t).ok(),
+      AlignmentSpecifier::Expr(e) => const_eval::integer_constant(e).ok().map(|n| n as u64),
+   }).max().unwrap_or(0);
+   for d in declaration.declarators.iter().filter(|d| !d.decl_type.is_function()) {
+      if !specifiers.is_constexpr {
+         let message = format!("Variable \"{}\": variables are not yet supported", d.name);
+         return Err(CompileError::codegen(ErrorCode::Unsupported, message).at(&d.span));
+      }
+      let natural = const_eval::align_of(&d.decl_type).unwrap_or(requested);
+      if requested > natural {
+         let message = format!("_Alignas({}) on \"{}\": over-aligned objects are not yet supported", requested, d.name);
+         return Err(CompileError::codegen(ErrorCode::Unsupported, message).at(&d.span));
+      }
+   }
+   Ok(())
+}
+
 pub enum FuncDef {
    Function(Function)
 }
@@ -41,27 +101,25 @@ impl FuncDef {
       println!("{:indent$})", "", indent=indent_level*3);
    }
 
-   pub fn parse(func_def: &ast::FuncDef) -> Result<FuncDef, String> {
-      match func_def {
-         ast::FuncDef::Function(f) => {
-            let mut tacky_function = Function::new(&f.name);
-            tacky_function.parse(f)?;
-            Ok(FuncDef::Function(tacky_function))
-         }
-      }
+   pub fn parse(function: &ast::Function, global: bool) -> Result<FuncDef, CompileError> {
+      let mut tacky_function = Function::new(&function.name, global);
+      tacky_function.parse(function)?;
+      Ok(FuncDef::Function(tacky_function))
    }
 }
 
 pub struct Function {
    pub identifier: String,
+   pub global: bool,
    pub instrs: Vec<Instr>,
    counter: usize
 }
 
 impl Function {
-   pub fn new(name: &String) -> Self {
+   pub fn new(name: &str, global_: bool) -> Self {
       Function {
          identifier: name.to_string(),
+         global: global_,
          instrs: Vec::new(),
          counter: 0
       }
@@ -69,48 +127,82 @@ impl Function {
 
    pub fn pretty_print(&self, indent_level: usize) {
       println!("{:indent$}name={name}", "", indent=indent_level*3, name=self.identifier);
+      println!("{:indent$}global={global}", "", indent=indent_level*3, global=self.global);
       println!("{:indent$}instrs=(size={size}) {instrs:?}", "", indent=indent_level*3, size=self.instrs.len(), instrs=self.instrs);
    }
 
-   pub fn parse(&mut self, function: &ast::Function) -> Result<(), String> {
-      match &function.stmt {
-         ast::Stmt::Return(e) => {
-            let ret = self.parse_expression(e)?;
-            self.instrs.push(Instr::Return(ret));
+   pub fn parse(&mut self, function: &ast::Function) -> Result<(), CompileError> {
+      let returns_int = match function.func_type.unqualified() {
+         Type::Function(ret, _) => matches!(ret.unqualified(), Type::Base(BaseType::Int)),
+         _ => false,
+      };
+      if !returns_int {
+         let message = format!("Function \"{}\": only functions returning int are supported", function.name);
+         return Err(CompileError::codegen(ErrorCode::Unsupported, message).at(&function.span));
+      }
+
+      for item in &function.body {
+         match item {
+            ast::BlockItem::Stmt(ast::Stmt { kind: ast::StmtKind::Return(e), span }) => {
+               let ret = self.parse_expression(e).map_err(|e| e.at(span))?;
+               self.instrs.push(Instr::Return(ret));
+            },
+            ast::BlockItem::Declaration(d) => check_declaration(d)?,
+            ast::BlockItem::StaticAssert(_) => (),
          }
       }
+      // Falling off the end of a function returns 0, as it must for main.
+      self.instrs.push(Instr::Return(Val::Constant(0)));
       Ok(())
    }
 
-   fn parse_expression(&mut self, expr: &ast::Expr) -> Result<Val, String> {
-      match expr {
-         ast::Expr::Const(c) => {
-            return Ok(Val::Constant(*c));
+   fn parse_expression(&mut self, expr: &ast::Expr) -> Result<Val, CompileError> {
+      match &expr.kind {
+         ast::ExprKind::Const(c) => {
+            match c {
+               // Everything is an int until there is a type checker, so wider constants are
+               // truncated the same way the implicit conversion to int would.
+               Constant::Integer(v, _) => Ok(Val::Constant(*v as u32 as u64)),
+               Constant::Floating(..) => Err(Self::unsupported_floating(expr)),
+            }
          },
-         ast::Expr::Unary(u) => {
-            let inner = match &**u {
-               ast::UnaryOp::Complement(e) => e,
-               ast::UnaryOp::Negate(e) => e
+         ast::ExprKind::Unary(u) => {
+            let (inner, tacky_op) = match &**u {
+               ast::UnaryOp::Complement(e) => (e, UnaryOp::Complement),
+               ast::UnaryOp::Negate(e) => (e, UnaryOp::Negate),
+               _ => return Self::fold_constant(expr),
             };
             let src: Val = self.parse_expression(inner)?;
             let dest_name: String = self.make_temporary();
             let dst = Val::Var(dest_name);
-            let tacky_op =  match &**u {
-               ast::UnaryOp::Complement(_) => UnaryOp::Complement,
-               ast::UnaryOp::Negate(_) => UnaryOp::Negate
-            };
             self.instrs.push(Instr::Unary(Unary { op:tacky_op, src, dst: dst.clone() }));
-            return Ok(dst);
+            Ok(dst)
          },
-         ast::Expr::Expr(e) => {
-            return self.parse_expression(&**e);
+         ast::ExprKind::Paren(e) => {
+            self.parse_expression(e)
          },
-         ast::Expr::Binary(_) => {
-            return Err(String::from("not yet impld"));
-         }
+         _ => Self::fold_constant(expr),
+      }
+   }
+
+   /// Operators without TACKY instructions yet are folded at compile time, which covers
+   /// every use of them while all operands are constants. The expression need not be a
+   /// constant expression, so undefined operations must not stop compilation.
+   /// The validator has already checked the operands, so folding can only fail on
+   /// something this compiler does not support.
+   fn fold_constant(expr: &ast::Expr) -> Result<Val, CompileError> {
+      let value = const_eval::fold(expr)
+         .map_err(|e| CompileError::codegen(ErrorCode::Unsupported, e).at(&expr.span))?;
+      match value {
+         Value::Integer(n, _) => Ok(Val::Constant(n as u32 as u64)),
+         Value::Floating(..) => Err(Self::unsupported_floating(expr)),
       }
    }
 
+   fn unsupported_floating(expr: &ast::Expr) -> CompileError {
+      CompileError::codegen(ErrorCode::Unsupported, "Floating-point values are not yet supported").at(&expr.span)
+   }
+
    fn make_temporary(&mut self) -> String {
       let tmp: String = format!("tmp.{}", self.counter);
       self.counter += 1;
@@ -143,3 +235,30 @@ pub enum Val {
    Constant(u64),
    Var(String)
 }
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::cli::Standard;
+   use crate::lexer::Lexer;
+
+   fn generate(source: &str) -> Result<Program, CompileError> {
+      let tokens = Lexer::new(source.to_string(), "test.c", Standard::C23).lex().expect("lexes");
+      let program = ast::Program::parse(&tokens, Standard::C23).expect("parses");
+      Program::parse(&program)
+   }
+
+   fn error_code(source: &str) -> Option<ErrorCode> {
+      generate(source).err().map(|e| e.diagnostic().code)
+   }
+
+   #[test]
+   fn over_aligned_objects_are_unsupported() {
+      assert_eq!(error_code("constexpr _Alignas(16) int x = 1;"), Some(ErrorCode::Unsupported));
+      assert_eq!(error_code("int main(void) { constexpr _Alignas(long) int x = 1; return x; }"), Some(ErrorCode::Unsupported));
+      assert_eq!(error_code("constexpr _Alignas(int) _Alignas(8) long x = 1;"), None);
+      assert_eq!(error_code("constexpr _Alignas(0) char c = 1;"), None);
+      assert_eq!(error_code("_Alignas(4) int x;"), Some(ErrorCode::Unsupported));
+      assert_eq!(error_code("typedef int T; extern int f(void);"), None);
+   }
+}